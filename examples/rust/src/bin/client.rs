@@ -11,14 +11,20 @@ use {
     yellowstone_grpc_client::{GeyserGrpcClient, GeyserGrpcClientError, Interceptor},
     yellowstone_grpc_proto::prelude::{
         subscribe_request_filter_accounts_filter::Filter as AccountsFilterDataOneof,
+        subscribe_request_filter_accounts_filter_lamports::Cmp as AccountsFilterLamports,
         subscribe_request_filter_accounts_filter_memcmp::Data as AccountsFilterMemcmpOneof,
-        subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
-        SubscribeRequestAccountsDataSlice, SubscribeRequestFilterAccounts,
-        SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
+        subscribe_request_filter_transactions_instruction::Data as TransactionsFilterInstructionDataOneof,
+        subscribe_update::UpdateOneof, CommitmentLevel, OverflowPolicy, SubscribeRequest,
+        SubscribeRequestAccountsCoalesce, SubscribeRequestAccountsDataSlice,
+        SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
+        SubscribeRequestFilterAccountsFilterDatasizeRange,
+        SubscribeRequestFilterAccountsFilterLamports, SubscribeRequestFilterAccountsFilterMemcmp,
         SubscribeRequestFilterBlocks, SubscribeRequestFilterBlocksMeta,
         SubscribeRequestFilterEntry, SubscribeRequestFilterSlots,
-        SubscribeRequestFilterTransactions, SubscribeRequestPing, SubscribeUpdateAccount,
-        SubscribeUpdateTransaction, SubscribeUpdateTransactionStatus,
+        SubscribeRequestFilterTransactions, SubscribeRequestFilterTransactionsInstruction,
+        SubscribeRequestFilterTransactionsTokenBalance, SubscribeRequestOverflow,
+        SubscribeRequestPing, SubscribeUpdateAccount, SubscribeUpdateTransaction,
+        SubscribeUpdateTransactionStatus,
     },
 };
 
@@ -83,6 +89,26 @@ impl From<ArgsCommitment> for CommitmentLevel {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum ArgsOverflowPolicy {
+    #[default]
+    Disconnect,
+    DropNewest,
+    DropOldest,
+    Sample,
+}
+
+impl From<ArgsOverflowPolicy> for OverflowPolicy {
+    fn from(policy: ArgsOverflowPolicy) -> Self {
+        match policy {
+            ArgsOverflowPolicy::Disconnect => OverflowPolicy::Disconnect,
+            ArgsOverflowPolicy::DropNewest => OverflowPolicy::DropNewest,
+            ArgsOverflowPolicy::DropOldest => OverflowPolicy::DropOldest,
+            ArgsOverflowPolicy::Sample => OverflowPolicy::Sample,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Subcommand)]
 enum Action {
     HealthCheck,
@@ -108,6 +134,10 @@ struct ActionSubscribe {
     #[clap(long)]
     accounts: bool,
 
+    /// Commitment level for accounts, request commitment is used by default
+    #[clap(long)]
+    accounts_commitment: Option<ArgsCommitment>,
+
     /// Filter by Account Pubkey
     #[clap(long)]
     accounts_account: Vec<String>,
@@ -128,10 +158,30 @@ struct ActionSubscribe {
     #[clap(long)]
     accounts_datasize: Option<u64>,
 
+    /// Filter by minimum Data size
+    #[clap(long)]
+    accounts_datasize_min: Option<u64>,
+
+    /// Filter by maximum Data size
+    #[clap(long)]
+    accounts_datasize_max: Option<u64>,
+
     /// Filter valid token accounts
     #[clap(long)]
     accounts_token_account_state: bool,
 
+    /// Filter token accounts by mint
+    #[clap(long)]
+    accounts_token_mint: Option<String>,
+
+    /// Filter token accounts by owner
+    #[clap(long)]
+    accounts_token_owner: Option<String>,
+
+    /// Filter by lamports, format: `eq:42` / `ne:42` / `lt:42` / `gt:42`
+    #[clap(long)]
+    accounts_lamports: Vec<String>,
+
     /// Receive only part of updated data account, format: `offset,size`
     #[clap(long)]
     accounts_data_slice: Vec<String>,
@@ -140,6 +190,10 @@ struct ActionSubscribe {
     #[clap(long)]
     slots: bool,
 
+    /// Commitment level for slots, request commitment is used by default
+    #[clap(long)]
+    slots_commitment: Option<ArgsCommitment>,
+
     /// Filter slots by commitment
     #[clap(long)]
     slots_filter_by_commitment: bool,
@@ -148,6 +202,10 @@ struct ActionSubscribe {
     #[clap(long)]
     transactions: bool,
 
+    /// Commitment level for transactions, request commitment is used by default
+    #[clap(long)]
+    transactions_commitment: Option<ArgsCommitment>,
+
     /// Filter vote transactions
     #[clap(long)]
     transactions_vote: Option<bool>,
@@ -172,10 +230,46 @@ struct ActionSubscribe {
     #[clap(long)]
     transactions_account_required: Vec<String>,
 
+    /// Filter included writable account in transactions
+    #[clap(long)]
+    transactions_account_include_writable: Vec<String>,
+
+    /// Filter included signer account in transactions
+    #[clap(long)]
+    transactions_account_include_signer: Vec<String>,
+
+    /// Filter required writable account in transactions
+    #[clap(long)]
+    transactions_account_required_writable: Vec<String>,
+
+    /// Filter required signer account in transactions
+    #[clap(long)]
+    transactions_account_required_signer: Vec<String>,
+
+    /// Filter transactions which invoke program, including inner instructions
+    #[clap(long)]
+    transactions_program_invoked: Vec<String>,
+
+    /// Filter transactions with log message containing substring
+    #[clap(long)]
+    transactions_log_contains: Vec<String>,
+
+    /// Filter transactions by instruction data prefix, format: `program,data in base58`
+    #[clap(long)]
+    transactions_instruction: Vec<String>,
+
+    /// Filter transactions by token balance change, format: `mint[,owner,...]`
+    #[clap(long)]
+    transactions_token_balance: Vec<String>,
+
     /// Subscribe on transactions_status updates
     #[clap(long)]
     transactions_status: bool,
 
+    /// Commitment level for transactions_status, request commitment is used by default
+    #[clap(long)]
+    transactions_status_commitment: Option<ArgsCommitment>,
+
     /// Filter vote transactions for transactions_status
     #[clap(long)]
     transactions_status_vote: Option<bool>,
@@ -200,13 +294,53 @@ struct ActionSubscribe {
     #[clap(long)]
     transactions_status_account_required: Vec<String>,
 
+    /// Filter included writable account in transactions for transactions_status
+    #[clap(long)]
+    transactions_status_account_include_writable: Vec<String>,
+
+    /// Filter included signer account in transactions for transactions_status
+    #[clap(long)]
+    transactions_status_account_include_signer: Vec<String>,
+
+    /// Filter required writable account in transactions for transactions_status
+    #[clap(long)]
+    transactions_status_account_required_writable: Vec<String>,
+
+    /// Filter required signer account in transactions for transactions_status
+    #[clap(long)]
+    transactions_status_account_required_signer: Vec<String>,
+
+    /// Filter transactions which invoke program for transactions_status
+    #[clap(long)]
+    transactions_status_program_invoked: Vec<String>,
+
+    /// Filter transactions with log message containing substring for transactions_status
+    #[clap(long)]
+    transactions_status_log_contains: Vec<String>,
+
+    /// Filter transactions by instruction data prefix for transactions_status
+    #[clap(long)]
+    transactions_status_instruction: Vec<String>,
+
+    /// Filter transactions by token balance change for transactions_status
+    #[clap(long)]
+    transactions_status_token_balance: Vec<String>,
+
     #[clap(long)]
     entry: bool,
 
+    /// Commitment level for entry, request commitment is used by default
+    #[clap(long)]
+    entry_commitment: Option<ArgsCommitment>,
+
     /// Subscribe on block updates
     #[clap(long)]
     blocks: bool,
 
+    /// Commitment level for blocks, request commitment is used by default
+    #[clap(long)]
+    blocks_commitment: Option<ArgsCommitment>,
+
     /// Filter included account in transactions
     #[clap(long)]
     blocks_account_include: Vec<String>,
@@ -227,15 +361,87 @@ struct ActionSubscribe {
     #[clap(long)]
     blocks_meta: bool,
 
+    /// Commitment level for blocks_meta, request commitment is used by default
+    #[clap(long)]
+    blocks_meta_commitment: Option<ArgsCommitment>,
+
     /// Send ping in subscribe request
     #[clap(long)]
     ping: Option<i32>,
 
+    /// Replay stored messages starting from the slot
+    #[clap(long)]
+    from_slot: Option<u64>,
+
+    /// Receive at most one account update per pubkey per slot
+    #[clap(long)]
+    accounts_coalesce: bool,
+
+    /// Receive at most one account update per pubkey per interval (in ms)
+    #[clap(long)]
+    accounts_coalesce_interval_ms: Option<u64>,
+
+    /// Policy for updates that do not fit into the stream
+    #[clap(long)]
+    overflow_policy: Option<ArgsOverflowPolicy>,
+
+    /// Max number of updates per second
+    #[clap(long)]
+    overflow_rate_limit: Option<u64>,
+
+    /// Send every N-th account update while stream is overflowed (sample policy)
+    #[clap(long)]
+    overflow_sample_rate: Option<u64>,
+
+    /// Receive compact notices at confirmed / finalized for accounts and transactions sent at processed
+    #[clap(long)]
+    commitment_upgrades: bool,
+
+    /// Receive updates for multiple commitment levels, replaces request commitment
+    #[clap(long)]
+    commitments: Vec<ArgsCommitment>,
+
+    /// Receive matched updates from one broadcast batch in one message
+    #[clap(long)]
+    batch: bool,
+
     // Resubscribe (only to slots) after
     #[clap(long)]
     resub: Option<usize>,
 }
 
+fn parse_transactions_instructions(
+    values: &[String],
+) -> anyhow::Result<Vec<SubscribeRequestFilterTransactionsInstruction>> {
+    values
+        .iter()
+        .map(|value| match value.split_once(',') {
+            Some((program_id, data)) => Ok(SubscribeRequestFilterTransactionsInstruction {
+                program_id: program_id.trim().to_string(),
+                data: Some(TransactionsFilterInstructionDataOneof::Base58(
+                    data.trim().to_string(),
+                )),
+            }),
+            None => anyhow::bail!("invalid instruction"),
+        })
+        .collect()
+}
+
+fn parse_transactions_token_balance(
+    values: &[String],
+) -> Vec<SubscribeRequestFilterTransactionsTokenBalance> {
+    values
+        .iter()
+        .map(|value| {
+            let mut items = value.split(',').map(|item| item.trim().to_string());
+            SubscribeRequestFilterTransactionsTokenBalance {
+                mint: items.next().unwrap_or_default(),
+                owner: items.collect(),
+            }
+        })
+        .collect()
+}
+
 impl Action {
     async fn get_subscribe_request(
         &self,
@@ -267,6 +473,7 @@ impl Action {
                                             data: Some(AccountsFilterMemcmpOneof::Base58(
                                                 data.trim().to_string(),
                                             )),
+                                            mask: None,
                                         },
                                     )),
                                 });
@@ -279,11 +486,55 @@ impl Action {
                             filter: Some(AccountsFilterDataOneof::Datasize(datasize)),
                         });
                     }
+                    if args.accounts_datasize_min.is_some() || args.accounts_datasize_max.is_some()
+                    {
+                        filters.push(SubscribeRequestFilterAccountsFilter {
+                            filter: Some(AccountsFilterDataOneof::DatasizeRange(
+                                SubscribeRequestFilterAccountsFilterDatasizeRange {
+                                    min: args.accounts_datasize_min,
+                                    max: args.accounts_datasize_max,
+                                },
+                            )),
+                        });
+                    }
                     if args.accounts_token_account_state {
                         filters.push(SubscribeRequestFilterAccountsFilter {
                             filter: Some(AccountsFilterDataOneof::TokenAccountState(true)),
                         });
                     }
+                    if let Some(token_mint) = args.accounts_token_mint.clone() {
+                        filters.push(SubscribeRequestFilterAccountsFilter {
+                            filter: Some(AccountsFilterDataOneof::TokenMint(token_mint)),
+                        });
+                    }
+                    if let Some(token_owner) = args.accounts_token_owner.clone() {
+                        filters.push(SubscribeRequestFilterAccountsFilter {
+                            filter: Some(AccountsFilterDataOneof::TokenOwner(token_owner)),
+                        });
+                    }
+                    for filter in args.accounts_lamports.iter() {
+                        match filter.split_once(':') {
+                            Some((cmp, value)) => {
+                                let value = value
+                                    .parse()
+                                    .map_err(|_| anyhow::anyhow!("invalid lamports value"))?;
+                                filters.push(SubscribeRequestFilterAccountsFilter {
+                                    filter: Some(AccountsFilterDataOneof::Lamports(
+                                        SubscribeRequestFilterAccountsFilterLamports {
+                                            cmp: Some(match cmp {
+                                                "eq" => AccountsFilterLamports::Eq(value),
+                                                "ne" => AccountsFilterLamports::Ne(value),
+                                                "lt" => AccountsFilterLamports::Lt(value),
+                                                "gt" => AccountsFilterLamports::Gt(value),
+                                                _ => anyhow::bail!("invalid lamports filter"),
+                                            }),
+                                        },
+                                    )),
+                                });
+                            }
+                            _ => anyhow::bail!("invalid lamports"),
+                        }
+                    }
 
                     accounts.insert(
                         "client".to_owned(),
@@ -291,6 +542,10 @@ impl Action {
                             account: accounts_account,
                             owner: args.accounts_owner.clone(),
                             filters,
+                            commitment: args
+                                .accounts_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
+                            data_slice: vec![],
                         },
                     );
                 }
@@ -301,6 +556,9 @@ impl Action {
                         "client".to_owned(),
                         SubscribeRequestFilterSlots {
                             filter_by_commitment: Some(args.slots_filter_by_commitment),
+                            commitment: args
+                                .slots_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
                         },
                     );
                 }
@@ -316,6 +574,29 @@ impl Action {
                             account_include: args.transactions_account_include.clone(),
                             account_exclude: args.transactions_account_exclude.clone(),
                             account_required: args.transactions_account_required.clone(),
+                            account_include_writable: args
+                                .transactions_account_include_writable
+                                .clone(),
+                            account_include_signer: args
+                                .transactions_account_include_signer
+                                .clone(),
+                            account_required_writable: args
+                                .transactions_account_required_writable
+                                .clone(),
+                            account_required_signer: args
+                                .transactions_account_required_signer
+                                .clone(),
+                            program_invoked: args.transactions_program_invoked.clone(),
+                            log_contains: args.transactions_log_contains.clone(),
+                            instructions: parse_transactions_instructions(
+                                &args.transactions_instruction,
+                            )?,
+                            token_balance: parse_transactions_token_balance(
+                                &args.transactions_token_balance,
+                            ),
+                            commitment: args
+                                .transactions_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
                         },
                     );
                 }
@@ -331,13 +612,43 @@ impl Action {
                             account_include: args.transactions_status_account_include.clone(),
                             account_exclude: args.transactions_status_account_exclude.clone(),
                             account_required: args.transactions_status_account_required.clone(),
+                            account_include_writable: args
+                                .transactions_status_account_include_writable
+                                .clone(),
+                            account_include_signer: args
+                                .transactions_status_account_include_signer
+                                .clone(),
+                            account_required_writable: args
+                                .transactions_status_account_required_writable
+                                .clone(),
+                            account_required_signer: args
+                                .transactions_status_account_required_signer
+                                .clone(),
+                            program_invoked: args.transactions_status_program_invoked.clone(),
+                            log_contains: args.transactions_status_log_contains.clone(),
+                            instructions: parse_transactions_instructions(
+                                &args.transactions_status_instruction,
+                            )?,
+                            token_balance: parse_transactions_token_balance(
+                                &args.transactions_status_token_balance,
+                            ),
+                            commitment: args
+                                .transactions_status_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
                         },
                     );
                 }
 
                 let mut entry: EntryFilterMap = HashMap::new();
                 if args.entry {
-                    entry.insert("client".to_owned(), SubscribeRequestFilterEntry {});
+                    entry.insert(
+                        "client".to_owned(),
+                        SubscribeRequestFilterEntry {
+                            commitment: args
+                                .entry_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
+                        },
+                    );
                 }
 
                 let mut blocks: BlocksFilterMap = HashMap::new();
@@ -349,13 +660,23 @@ impl Action {
                             include_transactions: args.blocks_include_transactions,
                             include_accounts: args.blocks_include_accounts,
                             include_entries: args.blocks_include_entries,
+                            commitment: args
+                                .blocks_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
                         },
                     );
                 }
 
                 let mut blocks_meta: BlocksMetaFilterMap = HashMap::new();
                 if args.blocks_meta {
-                    blocks_meta.insert("client".to_owned(), SubscribeRequestFilterBlocksMeta {});
+                    blocks_meta.insert(
+                        "client".to_owned(),
+                        SubscribeRequestFilterBlocksMeta {
+                            commitment: args
+                                .blocks_meta_commitment
+                                .map(|commitment| CommitmentLevel::from(commitment) as i32),
+                        },
+                    );
                 }
 
                 let mut accounts_data_slice = Vec::new();
@@ -383,9 +704,35 @@ impl Action {
                         entry,
                         blocks,
                         blocks_meta,
-                        commitment: commitment.map(|x| x as i32),
+                        commitment: if args.commitments.is_empty() {
+                            commitment.map(|x| x as i32)
+                        } else {
+                            None
+                        },
                         accounts_data_slice,
                         ping,
+                        from_slot: args.from_slot,
+                        accounts_coalesce: (args.accounts_coalesce
+                            || args.accounts_coalesce_interval_ms.is_some())
+                        .then_some(SubscribeRequestAccountsCoalesce {
+                            interval_ms: args.accounts_coalesce_interval_ms,
+                        }),
+                        overflow: (args.overflow_policy.is_some()
+                            || args.overflow_rate_limit.is_some()
+                            || args.overflow_sample_rate.is_some())
+                        .then(|| SubscribeRequestOverflow {
+                            policy: OverflowPolicy::from(args.overflow_policy.unwrap_or_default())
+                                as i32,
+                            rate_limit: args.overflow_rate_limit,
+                            sample_rate: args.overflow_sample_rate,
+                        }),
+                        commitment_upgrades: Some(args.commitment_upgrades),
+                        commitments: args
+                            .commitments
+                            .iter()
+                            .map(|commitment| CommitmentLevel::from(*commitment) as i32)
+                            .collect(),
+                        batch: Some(args.batch),
                     },
                     args.resub.unwrap_or(0),
                 ))
@@ -673,6 +1020,12 @@ async fn geyser_subscribe(
                     commitment: None,
                     accounts_data_slice: Vec::default(),
                     ping: None,
+                    from_slot: None,
+                    accounts_coalesce: None,
+                    overflow: None,
+                    commitment_upgrades: None,
+                    commitments: vec![],
+                    batch: None,
                 })
                 .await
                 .map_err(GeyserGrpcClientError::SubscribeSendError)?;
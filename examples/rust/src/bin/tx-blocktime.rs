@@ -104,13 +104,28 @@ async fn main() -> anyhow::Result<()> {
                 account_include: args.account_include,
                 account_exclude: args.account_exclude,
                 account_required: args.account_required,
+                account_include_writable: vec![],
+                account_include_signer: vec![],
+                account_required_writable: vec![],
+                account_required_signer: vec![],
+                program_invoked: vec![],
+                log_contains: vec![],
+                instructions: vec![],
+                token_balance: vec![],
+                commitment: None,
             } },
             entry: HashMap::new(),
             blocks: HashMap::new(),
-            blocks_meta: hashmap! { "".to_owned() => SubscribeRequestFilterBlocksMeta {} },
+            blocks_meta: hashmap! { "".to_owned() => SubscribeRequestFilterBlocksMeta { commitment: None } },
             commitment: Some(commitment as i32),
             accounts_data_slice: vec![],
             ping: None,
+            from_slot: None,
+            accounts_coalesce: None,
+            overflow: None,
+            commitment_upgrades: None,
+            commitments: vec![],
+            batch: None,
         })
         .await?;
 
@@ -1,4 +1,8 @@
-use {cargo_lock::Lockfile, std::collections::HashSet};
+use {
+    cargo_lock::Lockfile,
+    std::collections::HashSet,
+    tonic_build::manual::{Builder, Method, Service},
+};
 
 fn main() -> anyhow::Result<()> {
     vergen::Emitter::default()
@@ -23,9 +27,75 @@ fn main() -> anyhow::Result<()> {
         get_pkg_version(&lockfile, "yellowstone-grpc-proto")
     );
 
+    generate_grpc_geyser();
+
     Ok(())
 }
 
+// Server for `geyser.Geyser` with custom codec for pre-encoded `Subscribe` updates
+fn generate_grpc_geyser() {
+    let geyser_service = Service::builder()
+        .name("Geyser")
+        .package("geyser")
+        .method(
+            Method::builder()
+                .name("subscribe")
+                .route_name("Subscribe")
+                .input_type("yellowstone_grpc_proto::prelude::SubscribeRequest")
+                .output_type("crate::codec::FilteredUpdate")
+                .codec_path("crate::codec::SubscribeCodec")
+                .client_streaming()
+                .server_streaming()
+                .build(),
+        )
+        .method(unary_method("ping", "Ping", "PingRequest", "PongResponse"))
+        .method(unary_method(
+            "get_latest_blockhash",
+            "GetLatestBlockhash",
+            "GetLatestBlockhashRequest",
+            "GetLatestBlockhashResponse",
+        ))
+        .method(unary_method(
+            "get_block_height",
+            "GetBlockHeight",
+            "GetBlockHeightRequest",
+            "GetBlockHeightResponse",
+        ))
+        .method(unary_method(
+            "get_slot",
+            "GetSlot",
+            "GetSlotRequest",
+            "GetSlotResponse",
+        ))
+        .method(unary_method(
+            "is_blockhash_valid",
+            "IsBlockhashValid",
+            "IsBlockhashValidRequest",
+            "IsBlockhashValidResponse",
+        ))
+        .method(unary_method(
+            "get_version",
+            "GetVersion",
+            "GetVersionRequest",
+            "GetVersionResponse",
+        ))
+        .build();
+
+    Builder::new()
+        .build_client(false)
+        .compile(&[geyser_service]);
+}
+
+fn unary_method(name: &str, route_name: &str, input_type: &str, output_type: &str) -> Method {
+    Method::builder()
+        .name(name)
+        .route_name(route_name)
+        .input_type(format!("yellowstone_grpc_proto::prelude::{input_type}"))
+        .output_type(format!("yellowstone_grpc_proto::prelude::{output_type}"))
+        .codec_path("tonic::codec::ProstCodec")
+        .build()
+}
+
 fn get_pkg_version(lockfile: &Lockfile, pkg_name: &str) -> String {
     lockfile
         .packages
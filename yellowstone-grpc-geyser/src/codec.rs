@@ -0,0 +1,544 @@
+use {
+    crate::{
+        filters::FilterAccountsDataSlice,
+        grpc::{Message, MessageRef},
+    },
+    std::{
+        collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+        hash::{Hash, Hasher},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    },
+    tonic::{
+        codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+        Status,
+    },
+    yellowstone_grpc_proto::{
+        prelude::{subscribe_update::UpdateOneof, SubscribeRequest},
+        prost::{
+            bytes::{BufMut, Bytes},
+            encoding::{self, encode_key, encode_varint, encoded_len_varint, key_len, WireType},
+            Message as _,
+        },
+    },
+};
+
+pub mod geyser {
+    #![allow(clippy::clone_on_ref_ptr)]
+    #![allow(clippy::missing_const_for_fn)]
+
+    tonic::include_proto!("geyser.Geyser");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageEncodedKind {
+    Account,
+    Slot,
+    Transaction,
+    TransactionStatus,
+    Block,
+    BlockMeta,
+    Entry,
+}
+
+impl MessageEncodedKind {
+    // field numbers in `SubscribeUpdate`
+    const fn update_tag(self) -> u32 {
+        match self {
+            Self::Account => 2,
+            Self::Slot => 3,
+            Self::Transaction => 4,
+            Self::TransactionStatus => 10,
+            Self::Block => 5,
+            Self::BlockMeta => 7,
+            Self::Entry => 8,
+        }
+    }
+
+    // field numbers in `SubscribeUpdateBatchItem`
+    const fn batch_item_tag(self) -> u32 {
+        match self {
+            Self::Account => 2,
+            Self::Slot => 3,
+            Self::Transaction => 4,
+            Self::TransactionStatus => 5,
+            Self::Block => 6,
+            Self::BlockMeta => 7,
+            Self::Entry => 8,
+        }
+    }
+}
+
+/// Protobuf encoded update message (without field key), shared across clients
+#[derive(Debug, Clone)]
+pub struct MessageEncoded {
+    pub kind: MessageEncodedKind,
+    pub data: Bytes,
+}
+
+impl MessageEncoded {
+    pub fn new(message: &MessageRef<'_>, accounts_data_slice: &[FilterAccountsDataSlice]) -> Self {
+        let (kind, data) = match message.to_proto(accounts_data_slice) {
+            UpdateOneof::Account(msg) => (MessageEncodedKind::Account, msg.encode_to_vec()),
+            UpdateOneof::Slot(msg) => (MessageEncodedKind::Slot, msg.encode_to_vec()),
+            UpdateOneof::Transaction(msg) => (MessageEncodedKind::Transaction, msg.encode_to_vec()),
+            UpdateOneof::TransactionStatus(msg) => {
+                (MessageEncodedKind::TransactionStatus, msg.encode_to_vec())
+            }
+            UpdateOneof::Block(msg) => (MessageEncodedKind::Block, msg.encode_to_vec()),
+            UpdateOneof::BlockMeta(msg) => (MessageEncodedKind::BlockMeta, msg.encode_to_vec()),
+            UpdateOneof::Entry(msg) => (MessageEncodedKind::Entry, msg.encode_to_vec()),
+            _ => unreachable!("MessageRef is converted only to geyser messages"),
+        };
+        Self {
+            kind,
+            data: data.into(),
+        }
+    }
+}
+
+type MessagesEncodeCacheKey = (
+    usize, // address of the message
+    MessageEncodedKind,
+    [usize; 3], // number of transactions, accounts and entries in the block
+    Vec<FilterAccountsDataSlice>,
+);
+
+type MessagesEncodeCacheShard = Mutex<HashMap<MessagesEncodeCacheKey, MessageEncoded>>;
+
+// messages are spread by address, so clients encoding different messages do not wait each other
+const MESSAGES_ENCODE_CACHE_SHARDS: usize = 16;
+
+/// Encoded messages of one broadcasted batch, shared by all clients
+#[derive(Debug, Default)]
+pub struct MessagesEncodeCache {
+    shards: [MessagesEncodeCacheShard; MESSAGES_ENCODE_CACHE_SHARDS],
+    expired: AtomicBool,
+}
+
+impl MessagesEncodeCache {
+    pub fn get(
+        &self,
+        message: &Message,
+        message_ref: &MessageRef<'_>,
+        accounts_data_slice: &[FilterAccountsDataSlice],
+    ) -> MessageEncoded {
+        let Some(key) = Self::get_key(message, message_ref, accounts_data_slice) else {
+            return MessageEncoded::new(message_ref, accounts_data_slice);
+        };
+        if self.expired.load(Ordering::Relaxed) {
+            return MessageEncoded::new(message_ref, accounts_data_slice);
+        }
+
+        let shard = self.get_shard(key.0);
+        if let Some(encoded) = Self::lock(shard).get(&key) {
+            return encoded.clone();
+        }
+
+        // encode without lock, concurrent clients can encode the same message only once per race
+        let encoded = MessageEncoded::new(message_ref, accounts_data_slice);
+        let mut messages = Self::lock(shard);
+        if self.expired.load(Ordering::Relaxed) {
+            return encoded;
+        }
+        messages.entry(key).or_insert(encoded).clone()
+    }
+
+    // drop encoded messages, new messages are not cached anymore
+    pub fn expire(&self) {
+        self.expired.store(true, Ordering::Relaxed);
+        for shard in self.shards.iter() {
+            *Self::lock(shard) = HashMap::new();
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| Self::lock(shard).len())
+            .sum()
+    }
+
+    fn get_shard(&self, address: usize) -> &MessagesEncodeCacheShard {
+        let mut hasher = DefaultHasher::new();
+        address.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % MESSAGES_ENCODE_CACHE_SHARDS]
+    }
+
+    fn lock(
+        shard: &MessagesEncodeCacheShard,
+    ) -> std::sync::MutexGuard<'_, HashMap<MessagesEncodeCacheKey, MessageEncoded>> {
+        shard.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    // block is cached only if every list is empty or complete, partial lists depend on filter
+    fn get_key(
+        message: &Message,
+        message_ref: &MessageRef<'_>,
+        accounts_data_slice: &[FilterAccountsDataSlice],
+    ) -> Option<MessagesEncodeCacheKey> {
+        let address = message as *const Message as usize;
+        Some(match (message, message_ref) {
+            (Message::Account(_), MessageRef::Account(_)) => (
+                address,
+                MessageEncodedKind::Account,
+                [0; 3],
+                accounts_data_slice.to_vec(),
+            ),
+            (Message::Slot(_), MessageRef::Slot(_)) => {
+                (address, MessageEncodedKind::Slot, [0; 3], vec![])
+            }
+            (Message::Transaction(_), MessageRef::Transaction(_)) => {
+                (address, MessageEncodedKind::Transaction, [0; 3], vec![])
+            }
+            (Message::Transaction(_), MessageRef::TransactionStatus(_)) => (
+                address,
+                MessageEncodedKind::TransactionStatus,
+                [0; 3],
+                vec![],
+            ),
+            (Message::Entry(_), MessageRef::Entry(_)) => {
+                (address, MessageEncodedKind::Entry, [0; 3], vec![])
+            }
+            (Message::BlockMeta(_), MessageRef::BlockMeta(_)) => {
+                (address, MessageEncodedKind::BlockMeta, [0; 3], vec![])
+            }
+            (Message::Block(block), MessageRef::Block(block_ref)) => {
+                let lengths = [
+                    (block_ref.transactions.len(), block.transactions.len()),
+                    (block_ref.accounts.len(), block.accounts.len()),
+                    (block_ref.entries.len(), block.entries.len()),
+                ];
+                if lengths
+                    .iter()
+                    .any(|(len, total)| *len != 0 && *len != *total)
+                {
+                    return None;
+                }
+                (
+                    address,
+                    MessageEncodedKind::Block,
+                    lengths.map(|(len, _total)| len),
+                    accounts_data_slice.to_vec(),
+                )
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Caches of last broadcasted batches, encoded messages of older batches are dropped
+/// even if batches are still kept by the broadcast channel
+#[derive(Debug)]
+pub struct MessagesEncodeCaches {
+    caches: VecDeque<Arc<MessagesEncodeCache>>,
+    caches_max: usize,
+}
+
+impl MessagesEncodeCaches {
+    pub const fn new(caches_max: usize) -> Self {
+        Self {
+            caches: VecDeque::new(),
+            caches_max,
+        }
+    }
+
+    pub fn create(&mut self) -> Arc<MessagesEncodeCache> {
+        let cache = Arc::new(MessagesEncodeCache::default());
+        self.caches.push_back(Arc::clone(&cache));
+        while self.caches.len() > self.caches_max {
+            if let Some(cache) = self.caches.pop_front() {
+                cache.expire();
+            }
+        }
+        cache
+    }
+}
+
+/// Update sent to the client, data messages are pre-encoded
+#[derive(Debug, Clone)]
+pub struct FilteredUpdate {
+    pub filters: Vec<String>,
+    pub commitment: Option<i32>,
+    pub slot: Option<u64>, // max slot of messages in the update, not encoded
+    pub update_oneof: FilteredUpdateOneof,
+}
+
+#[derive(Debug, Clone)]
+pub enum FilteredUpdateOneof {
+    Encoded(MessageEncoded),
+    Batch {
+        filters: Vec<(String, u64)>, // (matched filter, number of items) for dropped updates, not encoded
+        items: Vec<(Vec<u32>, MessageEncoded)>, // (filter ids, message)
+    },
+    Update(UpdateOneof),
+}
+
+impl FilteredUpdate {
+    // field numbers in `SubscribeUpdate`
+    const TAG_FILTERS: u32 = 1;
+    const TAG_COMMITMENT: u32 = 14;
+    const TAG_BATCH: u32 = 15;
+    // field numbers in `SubscribeUpdateBatch` and `SubscribeUpdateBatchItem`
+    const TAG_BATCH_UPDATES: u32 = 1;
+    const TAG_BATCH_ITEM_FILTERS: u32 = 1;
+
+    pub const fn new(filters: Vec<String>, commitment: Option<i32>, update: UpdateOneof) -> Self {
+        Self {
+            filters,
+            commitment,
+            slot: None,
+            update_oneof: FilteredUpdateOneof::Update(update),
+        }
+    }
+
+    pub const fn is_account(&self) -> bool {
+        match &self.update_oneof {
+            FilteredUpdateOneof::Encoded(message) => {
+                matches!(message.kind, MessageEncodedKind::Account)
+            }
+            FilteredUpdateOneof::Update(update) => matches!(update, UpdateOneof::Account(_)),
+            FilteredUpdateOneof::Batch { .. } => false,
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        encoding::string::encoded_len_repeated(Self::TAG_FILTERS, &self.filters)
+            + self.commitment.map_or(0, |commitment| {
+                encoding::int32::encoded_len(Self::TAG_COMMITMENT, &commitment)
+            })
+            + match &self.update_oneof {
+                FilteredUpdateOneof::Encoded(message) => {
+                    Self::message_len(message.kind.update_tag(), message.data.len())
+                }
+                FilteredUpdateOneof::Batch { items, .. } => {
+                    Self::message_len(Self::TAG_BATCH, Self::batch_len(items))
+                }
+                FilteredUpdateOneof::Update(update) => update.encoded_len(),
+            }
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) {
+        encoding::string::encode_repeated(Self::TAG_FILTERS, &self.filters, buf);
+        if let Some(commitment) = self.commitment {
+            encoding::int32::encode(Self::TAG_COMMITMENT, &commitment, buf);
+        }
+        match &self.update_oneof {
+            FilteredUpdateOneof::Encoded(message) => {
+                Self::encode_message(message.kind.update_tag(), &message.data, buf);
+            }
+            FilteredUpdateOneof::Batch { items, .. } => {
+                encode_key(Self::TAG_BATCH, WireType::LengthDelimited, buf);
+                encode_varint(Self::batch_len(items) as u64, buf);
+                for (filters, message) in items {
+                    encode_key(Self::TAG_BATCH_UPDATES, WireType::LengthDelimited, buf);
+                    encode_varint(Self::batch_item_len(filters, message) as u64, buf);
+                    encoding::uint32::encode_packed(Self::TAG_BATCH_ITEM_FILTERS, filters, buf);
+                    Self::encode_message(message.kind.batch_item_tag(), &message.data, buf);
+                }
+            }
+            FilteredUpdateOneof::Update(update) => update.encode(buf),
+        }
+    }
+
+    fn batch_len(items: &[(Vec<u32>, MessageEncoded)]) -> usize {
+        items
+            .iter()
+            .map(|(filters, message)| {
+                Self::message_len(
+                    Self::TAG_BATCH_UPDATES,
+                    Self::batch_item_len(filters, message),
+                )
+            })
+            .sum()
+    }
+
+    fn batch_item_len(filters: &[u32], message: &MessageEncoded) -> usize {
+        encoding::uint32::encoded_len_packed(Self::TAG_BATCH_ITEM_FILTERS, filters)
+            + Self::message_len(message.kind.batch_item_tag(), message.data.len())
+    }
+
+    fn message_len(tag: u32, len: usize) -> usize {
+        key_len(tag) + encoded_len_varint(len as u64) + len
+    }
+
+    fn encode_message(tag: u32, data: &[u8], buf: &mut impl BufMut) {
+        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_varint(data.len() as u64, buf);
+        buf.put_slice(data);
+    }
+}
+
+/// Codec for `Subscribe` method, writes pre-encoded messages directly into the stream
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SubscribeCodec;
+
+impl Codec for SubscribeCodec {
+    type Encode = FilteredUpdate;
+    type Decode = SubscribeRequest;
+
+    type Encoder = Self;
+    type Decoder = Self;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        *self
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        *self
+    }
+}
+
+impl Encoder for SubscribeCodec {
+    type Item = FilteredUpdate;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(buf);
+        Ok(())
+    }
+}
+
+impl Decoder for SubscribeCodec {
+    type Item = SubscribeRequest;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        SubscribeRequest::decode(buf)
+            .map(Some)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{MessagesEncodeCache, MessagesEncodeCaches},
+        crate::{
+            filters::FilterAccountsDataSlice,
+            grpc::{Message, MessageAccount, MessageAccountInfo, MessageBlock, MessageRef},
+        },
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    fn create_account_info(data: Vec<u8>) -> MessageAccountInfo {
+        MessageAccountInfo {
+            pubkey: Pubkey::new_unique(),
+            lamports: 0,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+            data,
+            write_version: 1,
+            txn_signature: None,
+        }
+    }
+
+    fn create_message_block(accounts: Vec<MessageAccountInfo>) -> Message {
+        Message::Block(MessageBlock {
+            parent_slot: 99,
+            slot: 100,
+            parent_blockhash: String::new(),
+            blockhash: String::new(),
+            rewards: vec![],
+            num_partitions: None,
+            block_time: None,
+            block_height: None,
+            executed_transaction_count: 0,
+            transactions: vec![],
+            updated_account_count: accounts.len() as u64,
+            accounts,
+            entries_count: 0,
+            entries: vec![],
+        })
+    }
+
+    #[test]
+    fn test_cache_accounts_data_slice() {
+        let cache = MessagesEncodeCache::default();
+        let message = Message::Account(MessageAccount {
+            account: create_account_info(vec![1, 2, 3]),
+            slot: 100,
+            is_startup: false,
+        });
+        let Message::Account(account) = &message else {
+            unreachable!();
+        };
+        let message_ref = MessageRef::Account(account);
+        let data_slice = [FilterAccountsDataSlice {
+            start: 0,
+            end: 1,
+            length: 1,
+        }];
+
+        let full = cache.get(&message, &message_ref, &[]);
+        let sliced = cache.get(&message, &message_ref, &data_slice);
+        assert_ne!(full.data, sliced.data);
+        assert_eq!(cache.len(), 2);
+
+        // same data slice share encoded message
+        let sliced_again = cache.get(&message, &message_ref, &data_slice);
+        assert_eq!(sliced.data.as_ptr(), sliced_again.data.as_ptr());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_cache_block_partial() {
+        let cache = MessagesEncodeCache::default();
+        let message = create_message_block(vec![
+            create_account_info(vec![1]),
+            create_account_info(vec![2]),
+        ]);
+        let Message::Block(block) = &message else {
+            unreachable!();
+        };
+        let accounts = block.accounts.iter().collect::<Vec<_>>();
+
+        let full_ref = MessageRef::Block((block, vec![], accounts.clone(), vec![]).into());
+        let full = cache.get(&message, &full_ref, &[]);
+        let empty_ref = MessageRef::Block((block, vec![], vec![], vec![]).into());
+        let empty = cache.get(&message, &empty_ref, &[]);
+        assert_ne!(full.data, empty.data);
+        assert_eq!(cache.len(), 2);
+
+        // partially filtered blocks depend on filter and never cached
+        let partial_ref = MessageRef::Block((block, vec![], accounts[..1].to_vec(), vec![]).into());
+        let partial = cache.get(&message, &partial_ref, &[]);
+        assert_ne!(partial.data, full.data);
+        assert_ne!(partial.data, empty.data);
+        let partial_ref = MessageRef::Block((block, vec![], accounts[1..].to_vec(), vec![]).into());
+        let partial_other = cache.get(&message, &partial_ref, &[]);
+        assert_ne!(partial.data, partial_other.data);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_caches_expire() {
+        let message = create_message_block(vec![]);
+        let Message::Block(block) = &message else {
+            unreachable!();
+        };
+        let message_ref = MessageRef::Block((block, vec![], vec![], vec![]).into());
+
+        let mut caches = MessagesEncodeCaches::new(2);
+        let first = caches.create();
+        first.get(&message, &message_ref, &[]);
+        assert_eq!(first.len(), 1);
+        caches.create();
+        assert_eq!(first.len(), 1);
+
+        // only last batches keep encoded messages
+        caches.create();
+        assert_eq!(first.len(), 0);
+        first.get(&message, &message_ref, &[]);
+        assert_eq!(first.len(), 0);
+
+        // zero disables the cache
+        let cache = MessagesEncodeCaches::new(0).create();
+        cache.get(&message, &message_ref, &[]);
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -4,7 +4,7 @@ use {
     },
     serde::{de, Deserialize, Deserializer},
     solana_sdk::pubkey::Pubkey,
-    std::{collections::HashSet, fs::read_to_string, net::SocketAddr, path::Path},
+    std::{collections::HashSet, fs::read_to_string, net::SocketAddr, path::Path, time::Duration},
     tokio::sync::Semaphore,
     tonic::codec::CompressionEncoding,
 };
@@ -28,9 +28,18 @@ pub struct Config {
 
 impl Config {
     fn load_from_str(config: &str) -> PluginResult<Self> {
-        serde_json::from_str(config).map_err(|error| GeyserPluginError::ConfigFileReadError {
-            msg: error.to_string(),
-        })
+        let config: Self = serde_json::from_str(config).map_err(|error| {
+            GeyserPluginError::ConfigFileReadError {
+                msg: error.to_string(),
+            }
+        })?;
+        config
+            .grpc
+            .check()
+            .map_err(|error| GeyserPluginError::ConfigFileReadError {
+                msg: error.to_string(),
+            })?;
+        Ok(config)
     }
 
     pub fn load_from_file<P: AsRef<Path>>(file: P) -> PluginResult<Self> {
@@ -105,11 +114,26 @@ pub struct ConfigGrpc {
     /// Enable/disable unary methods
     #[serde(default)]
     pub unary_disabled: bool,
+    /// Number of slots for which messages are kept for replay with `from_slot`, 0 disables replay
+    #[serde(default)]
+    pub replay_stored_slots: u64,
+    /// Runtime and batching options of geyser messages loop
+    #[serde(default)]
+    pub geyser_loop: ConfigGrpcGeyserLoop,
+    /// Interval of ping messages to clients, in milliseconds
+    #[serde(default = "ConfigGrpc::ping_interval_ms_default")]
+    pub ping_interval_ms: u64,
+    /// Limits of clients with overflow policies
+    #[serde(default)]
+    pub overflow: ConfigGrpcOverflow,
     /// Limits for possible filters
     #[serde(default)]
     pub filters: ConfigGrpcFilters,
     /// x_token to enforce on connections
     pub x_token: Option<String>,
+    /// Named tokens with own limits, connections with `x_token` use global limits
+    #[serde(default)]
+    pub x_tokens: Vec<ConfigGrpcXToken>,
 }
 
 impl ConfigGrpc {
@@ -132,6 +156,176 @@ impl ConfigGrpc {
     const fn unary_concurrency_limit_default() -> usize {
         Semaphore::MAX_PERMITS
     }
+
+    const fn ping_interval_ms_default() -> u64 {
+        10_000
+    }
+
+    pub const fn ping_interval(&self) -> Duration {
+        Duration::from_millis(self.ping_interval_ms)
+    }
+
+    pub fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.ping_interval_ms > 0,
+            "`ping_interval_ms` should be positive"
+        );
+        self.geyser_loop.check()?;
+        self.overflow.check()?;
+
+        let mut names = HashSet::new();
+        let mut tokens = HashSet::new();
+        tokens.extend(self.x_token.as_deref());
+        for x_token in self.x_tokens.iter() {
+            anyhow::ensure!(!x_token.name.is_empty(), "`x_tokens` name can not be empty");
+            anyhow::ensure!(
+                x_token.name != ConfigGrpcXToken::DEFAULT_NAME,
+                "`x_tokens` name {} is reserved",
+                ConfigGrpcXToken::DEFAULT_NAME
+            );
+            anyhow::ensure!(
+                names.insert(x_token.name.as_str()),
+                "`x_tokens` name {} is not unique",
+                x_token.name
+            );
+            anyhow::ensure!(
+                tokens.insert(x_token.token.as_str()),
+                "`x_tokens` token of {} is not unique",
+                x_token.name
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigGrpcXToken {
+    /// Name of the token, used as label in metrics
+    pub name: String,
+    /// Value of `x-token` header
+    pub token: String,
+    /// Limits for possible filters, global `filters` are used if not set
+    #[serde(default)]
+    pub filters: Option<ConfigGrpcFilters>,
+    /// Max number of concurrent subscriptions
+    #[serde(
+        default = "ConfigGrpcXToken::subscriptions_max_default",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub subscriptions_max: usize,
+    /// Allowed unary methods, all methods are allowed if not set
+    #[serde(default)]
+    pub unary_allowed: Option<HashSet<ConfigGrpcUnaryMethod>>,
+}
+
+impl ConfigGrpcXToken {
+    /// Name of `x_token` and connections without auth in metrics
+    pub const DEFAULT_NAME: &'static str = "default";
+
+    const fn subscriptions_max_default() -> usize {
+        usize::MAX
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigGrpcUnaryMethod {
+    Ping,
+    GetLatestBlockhash,
+    GetBlockHeight,
+    GetSlot,
+    IsBlockhashValid,
+    GetVersion,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigGrpcGeyserLoop {
+    /// Number of worker threads in the runtime of geyser loop
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub worker_threads: usize,
+    /// Max number of processed messages in one broadcasted batch
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub processed_messages_max: usize,
+    /// Max delay of processed messages before broadcast, in milliseconds
+    pub processed_messages_sleep_ms: u64,
+    /// Number of slots before finalized for which block reconstruction info is kept
+    pub keep_slots_before_finalized: u64,
+    /// Number of last broadcasted batches which keep encoded messages, 0 disables the cache
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub encoded_batches_max: usize,
+}
+
+impl Default for ConfigGrpcGeyserLoop {
+    fn default() -> Self {
+        Self {
+            worker_threads: 4,
+            processed_messages_max: 31,
+            processed_messages_sleep_ms: 10,
+            keep_slots_before_finalized: 10,
+            encoded_batches_max: 256,
+        }
+    }
+}
+
+impl ConfigGrpcGeyserLoop {
+    pub const fn processed_messages_sleep(&self) -> Duration {
+        Duration::from_millis(self.processed_messages_sleep_ms)
+    }
+
+    pub fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.worker_threads > 0,
+            "`geyser_loop.worker_threads` should be positive"
+        );
+        anyhow::ensure!(
+            self.processed_messages_max > 0,
+            "`geyser_loop.processed_messages_max` should be positive"
+        );
+        anyhow::ensure!(
+            self.processed_messages_sleep_ms > 0,
+            "`geyser_loop.processed_messages_sleep_ms` should be positive"
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigGrpcOverflow {
+    /// Max number of buffered updates per client with `drop_oldest` policy
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub buffer_max: usize,
+    /// Interval of sending buffered updates and dropped notice, in milliseconds
+    pub flush_interval_ms: u64,
+}
+
+impl Default for ConfigGrpcOverflow {
+    fn default() -> Self {
+        Self {
+            buffer_max: 1_024,
+            flush_interval_ms: 10,
+        }
+    }
+}
+
+impl ConfigGrpcOverflow {
+    pub const fn flush_interval(&self) -> Duration {
+        Duration::from_millis(self.flush_interval_ms)
+    }
+
+    pub fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.buffer_max > 0,
+            "`overflow.buffer_max` should be positive"
+        );
+        anyhow::ensure!(
+            self.flush_interval_ms > 0,
+            "`overflow.flush_interval_ms` should be positive"
+        );
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -249,6 +443,11 @@ pub struct ConfigGrpcFiltersAccounts {
     pub owner_max: usize,
     #[serde(deserialize_with = "deserialize_pubkey_set")]
     pub owner_reject: HashSet<Pubkey>,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub filters_max: usize,
+    /// Max depth of nested `any_of` / `not` filters
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub nested_max: usize,
 }
 
 impl Default for ConfigGrpcFiltersAccounts {
@@ -260,6 +459,8 @@ impl Default for ConfigGrpcFiltersAccounts {
             account_reject: HashSet::new(),
             owner_max: usize::MAX,
             owner_reject: HashSet::new(),
+            filters_max: 4,
+            nested_max: 2,
         }
     }
 }
@@ -291,6 +492,18 @@ pub struct ConfigGrpcFiltersTransactions {
     pub account_exclude_max: usize,
     #[serde(deserialize_with = "deserialize_usize_str")]
     pub account_required_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub program_invoked_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub log_contains_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub log_contains_length_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub instructions_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub token_balance_max: usize,
+    #[serde(deserialize_with = "deserialize_usize_str")]
+    pub token_balance_owner_max: usize,
 }
 
 impl Default for ConfigGrpcFiltersTransactions {
@@ -302,6 +515,12 @@ impl Default for ConfigGrpcFiltersTransactions {
             account_include_reject: HashSet::new(),
             account_exclude_max: usize::MAX,
             account_required_max: usize::MAX,
+            program_invoked_max: usize::MAX,
+            log_contains_max: usize::MAX,
+            log_contains_length_max: 256,
+            instructions_max: usize::MAX,
+            token_balance_max: usize::MAX,
+            token_balance_owner_max: usize::MAX,
         }
     }
 }
@@ -436,3 +655,93 @@ where
         })
         .collect::<Result<_, _>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::ConfigGrpc, serde_json::json};
+
+    fn parse_config(options: serde_json::Value) -> serde_json::Result<ConfigGrpc> {
+        let mut config = json!({ "address": "127.0.0.1:10000" });
+        if let (Some(config), Some(options)) = (config.as_object_mut(), options.as_object()) {
+            config.extend(options.clone());
+        }
+        serde_json::from_str(&config.to_string())
+    }
+
+    fn create_config(options: serde_json::Value) -> ConfigGrpc {
+        parse_config(options).expect("valid config")
+    }
+
+    #[test]
+    fn test_config_geyser_loop() {
+        let config = create_config(json!({}));
+        assert!(config.check().is_ok());
+        assert_eq!(config.geyser_loop.worker_threads, 4);
+        assert_eq!(config.geyser_loop.processed_messages_max, 31);
+        assert_eq!(config.ping_interval_ms, 10_000);
+
+        let config = create_config(json!({
+            "geyser_loop": {
+                "worker_threads": "8",
+                "processed_messages_max": "1_000",
+                "processed_messages_sleep_ms": 1,
+                "encoded_batches_max": 0
+            },
+            "ping_interval_ms": 1
+        }));
+        assert!(config.check().is_ok());
+        assert_eq!(config.geyser_loop.worker_threads, 8);
+        assert_eq!(config.geyser_loop.processed_messages_max, 1_000);
+        assert_eq!(config.geyser_loop.keep_slots_before_finalized, 10);
+
+        // zero values are parsed, but rejected by check
+        for options in [
+            json!({ "geyser_loop": { "worker_threads": 0 } }),
+            json!({ "geyser_loop": { "processed_messages_max": "0" } }),
+            json!({ "geyser_loop": { "processed_messages_sleep_ms": 0 } }),
+            json!({ "ping_interval_ms": 0 }),
+            json!({ "overflow": { "buffer_max": 0 } }),
+            json!({ "overflow": { "flush_interval_ms": 0 } }),
+        ] {
+            assert!(create_config(options).check().is_err());
+        }
+
+        // invalid values are not parsed
+        for options in [
+            json!({ "geyser_loop": { "worker_threads": -1 } }),
+            json!({ "geyser_loop": { "worker_threads": "four" } }),
+            json!({ "geyser_loop": { "processed_messages_max": 1.5 } }),
+            json!({ "geyser_loop": { "processed_messages_sleep_ms": "10" } }),
+            json!({ "geyser_loop": { "unknown": 1 } }),
+            json!({ "ping_interval_ms": -1 }),
+            json!({ "ping_interval_ms": null }),
+        ] {
+            assert!(parse_config(options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_x_tokens() {
+        let config = create_config(json!({
+            "x_token": "secret",
+            "x_tokens": [
+                { "name": "a", "token": "token-a", "subscriptions_max": "1_000", "unary_allowed": ["ping", "get_slot"] },
+                { "name": "b", "token": "token-b" }
+            ]
+        }));
+        assert!(config.check().is_ok());
+        assert_eq!(config.x_tokens[0].subscriptions_max, 1_000);
+        assert_eq!(config.x_tokens[1].subscriptions_max, usize::MAX);
+        assert!(config.x_tokens[1].unary_allowed.is_none());
+
+        for options in [
+            json!({ "x_tokens": [{ "name": "", "token": "token-a" }] }),
+            json!({ "x_tokens": [{ "name": "default", "token": "token-a" }] }),
+            json!({ "x_tokens": [{ "name": "a", "token": "token-a" }, { "name": "a", "token": "token-b" }] }),
+            json!({ "x_tokens": [{ "name": "a", "token": "token-a" }, { "name": "b", "token": "token-a" }] }),
+            json!({ "x_token": "token-a", "x_tokens": [{ "name": "a", "token": "token-a" }] }),
+        ] {
+            assert!(create_config(options).check().is_err());
+        }
+    }
+}
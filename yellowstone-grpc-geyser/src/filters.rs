@@ -1,5 +1,6 @@
 use {
     crate::{
+        codec::{FilteredUpdate, FilteredUpdateOneof, MessagesEncodeCache},
         config::{
             ConfigGrpcFilters, ConfigGrpcFiltersAccounts, ConfigGrpcFiltersBlocks,
             ConfigGrpcFiltersBlocksMeta, ConfigGrpcFiltersEntry, ConfigGrpcFiltersSlots,
@@ -10,21 +11,37 @@ use {
             MessageSlot, MessageTransaction,
         },
     },
+    arc_swap::ArcSwap,
     base64::{engine::general_purpose::STANDARD as base64_engine, Engine},
-    solana_sdk::{pubkey::Pubkey, signature::Signature},
-    spl_token_2022::{generic_token_account::GenericTokenAccount, state::Account as TokenAccount},
+    solana_sdk::{
+        instruction::CompiledInstruction, message::SanitizedMessage, pubkey::Pubkey,
+        signature::Signature,
+    },
+    solana_transaction_status::{TransactionStatusMeta, TransactionTokenBalance},
+    spl_token_2022::{
+        extension::StateWithExtensions, generic_token_account::GenericTokenAccount,
+        state::Account as TokenAccount,
+    },
     std::{
-        collections::{HashMap, HashSet},
+        collections::{BTreeMap, HashMap, HashSet},
         str::FromStr,
+        sync::{Arc, RwLock},
+        time::Duration,
     },
     yellowstone_grpc_proto::prelude::{
         subscribe_request_filter_accounts_filter::Filter as AccountsFilterDataOneof,
+        subscribe_request_filter_accounts_filter_lamports::Cmp as AccountsFilterLamports,
         subscribe_request_filter_accounts_filter_memcmp::Data as AccountsFilterMemcmpOneof,
-        subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
-        SubscribeRequestAccountsDataSlice, SubscribeRequestFilterAccounts,
-        SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterBlocks,
+        subscribe_request_filter_transactions_instruction::Data as TransactionsFilterInstructionDataOneof,
+        subscribe_update::UpdateOneof, CommitmentLevel, OverflowPolicy, SubscribeRequest,
+        SubscribeRequestAccountsCoalesce, SubscribeRequestAccountsDataSlice,
+        SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
+        SubscribeRequestFilterAccountsFilterLamports, SubscribeRequestFilterBlocks,
         SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterEntry, SubscribeRequestFilterSlots,
-        SubscribeRequestFilterTransactions, SubscribeUpdate, SubscribeUpdatePong,
+        SubscribeRequestFilterTransactions, SubscribeRequestFilterTransactionsInstruction,
+        SubscribeRequestFilterTransactionsTokenBalance, SubscribeRequestOverflow,
+        SubscribeUpdateBatchFilters, SubscribeUpdateCommitmentUpgrade,
+        SubscribeUpdateCommitmentUpgradeAccount, SubscribeUpdatePong,
     },
 };
 
@@ -37,12 +54,21 @@ pub struct Filter {
     entry: FilterEntry,
     blocks: FilterBlocks,
     blocks_meta: FilterBlocksMeta,
-    commitment: CommitmentLevel,
+    commitments: FilterCommitments,
     accounts_data_slice: Vec<FilterAccountsDataSlice>,
     ping: Option<i32>,
+    from_slot: Option<u64>,
+    accounts_coalesce: Option<FilterAccountsCoalesce>,
+    overflow: FilterOverflow,
+    commitment_upgrades: bool,
+    batch: Option<Vec<String>>,
 }
 
 impl Filter {
+    const MAX_DATA_SIZE: usize = 128;
+    const MAX_DATA_BASE58_SIZE: usize = 175;
+    const MAX_DATA_BASE64_SIZE: usize = 172;
+
     pub fn new(config: &SubscribeRequest, limit: &ConfigGrpcFilters) -> anyhow::Result<Self> {
         Ok(Self {
             accounts: FilterAccounts::new(&config.accounts, &limit.accounts)?,
@@ -60,12 +86,41 @@ impl Filter {
             entry: FilterEntry::new(&config.entry, &limit.entry)?,
             blocks: FilterBlocks::new(&config.blocks, &limit.blocks)?,
             blocks_meta: FilterBlocksMeta::new(&config.blocks_meta, &limit.blocks_meta)?,
-            commitment: Self::decode_commitment(config.commitment)?,
+            commitments: FilterCommitments::new(
+                config,
+                &Self::decode_commitments(config.commitment, &config.commitments)?,
+            )?,
             accounts_data_slice: FilterAccountsDataSlice::create(&config.accounts_data_slice)?,
             ping: config.ping.as_ref().map(|msg| msg.id),
+            from_slot: config.from_slot,
+            accounts_coalesce: FilterAccountsCoalesce::new(config.accounts_coalesce.as_ref())?,
+            overflow: FilterOverflow::new(config.overflow.as_ref())?,
+            commitment_upgrades: config.commitment_upgrades.unwrap_or(false),
+            batch: config
+                .batch
+                .unwrap_or(false)
+                .then(|| Self::decode_batch_filters(config)),
         })
     }
 
+    // filter id is the index in the sorted list of filter names
+    fn decode_batch_filters(config: &SubscribeRequest) -> Vec<String> {
+        let mut filters = config
+            .accounts
+            .keys()
+            .chain(config.slots.keys())
+            .chain(config.transactions.keys())
+            .chain(config.transactions_status.keys())
+            .chain(config.entry.keys())
+            .chain(config.blocks.keys())
+            .chain(config.blocks_meta.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        filters.sort();
+        filters.dedup();
+        filters
+    }
+
     fn decode_commitment(commitment: Option<i32>) -> anyhow::Result<CommitmentLevel> {
         let commitment = commitment.unwrap_or(CommitmentLevel::Processed as i32);
         CommitmentLevel::try_from(commitment).map_err(|_error| {
@@ -73,6 +128,27 @@ impl Filter {
         })
     }
 
+    fn decode_commitments(
+        commitment: Option<i32>,
+        commitments: &[i32],
+    ) -> anyhow::Result<Vec<CommitmentLevel>> {
+        if commitments.is_empty() {
+            return Ok(vec![Self::decode_commitment(commitment)?]);
+        }
+
+        anyhow::ensure!(
+            commitment.is_none(),
+            "commitment and commitments are mutually exclusive"
+        );
+        let mut commitments = commitments
+            .iter()
+            .map(|commitment| Self::decode_commitment(Some(*commitment)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        commitments.sort();
+        commitments.dedup();
+        Ok(commitments)
+    }
+
     fn decode_pubkeys<'a>(
         pubkeys: &'a [String],
         limit: &'a HashSet<Pubkey>,
@@ -96,6 +172,20 @@ impl Filter {
         Ok(vec)
     }
 
+    fn decode_data_base58(data: &str) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(data.len() <= Self::MAX_DATA_BASE58_SIZE, "data too large");
+        bs58::decode(data)
+            .into_vec()
+            .map_err(|_| anyhow::anyhow!("invalid base58"))
+    }
+
+    fn decode_data_base64(data: &str) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(data.len() <= Self::MAX_DATA_BASE64_SIZE, "data too large");
+        base64_engine
+            .decode(data)
+            .map_err(|_| anyhow::anyhow!("invalid base64"))
+    }
+
     pub fn get_metrics(&self) -> [(&'static str, usize); 8] {
         [
             ("accounts", self.accounts.filters.len()),
@@ -121,8 +211,24 @@ impl Filter {
         ]
     }
 
-    pub const fn get_commitment_level(&self) -> CommitmentLevel {
-        self.commitment
+    pub fn contains_commitment_level(&self, commitment: CommitmentLevel) -> bool {
+        self.commitments.levels.contains(&commitment)
+    }
+
+    pub fn get_commitment_levels(&self) -> &[CommitmentLevel] {
+        &self.commitments.levels
+    }
+
+    pub const fn get_from_slot(&self) -> Option<u64> {
+        self.from_slot
+    }
+
+    pub const fn get_accounts_coalesce(&self) -> Option<FilterAccountsCoalesce> {
+        self.accounts_coalesce
+    }
+
+    pub const fn get_overflow(&self) -> FilterOverflow {
+        self.overflow
     }
 
     pub fn get_filters<'a>(
@@ -130,17 +236,45 @@ impl Filter {
         message: &'a Message,
         commitment: Option<CommitmentLevel>,
     ) -> Box<dyn Iterator<Item = (Vec<String>, MessageRef<'a>)> + Send + 'a> {
+        let commitments = &self.commitments;
         match message {
-            Message::Account(message) => self.accounts.get_filters(message),
-            Message::Slot(message) => self.slots.get_filters(message, commitment),
+            Message::Account(message) => FilterCommitments::retain(
+                &commitments.accounts,
+                commitment,
+                self.accounts.get_filters(message),
+            ),
+            Message::Slot(message) => FilterCommitments::retain(
+                &commitments.slots,
+                commitment,
+                self.slots.get_filters(message, commitment),
+            ),
             Message::Transaction(message) => Box::new(
-                self.transactions
-                    .get_filters(message)
-                    .chain(self.transactions_status.get_filters(message)),
+                FilterCommitments::retain(
+                    &commitments.transactions,
+                    commitment,
+                    self.transactions.get_filters(message),
+                )
+                .chain(FilterCommitments::retain(
+                    &commitments.transactions_status,
+                    commitment,
+                    self.transactions_status.get_filters(message),
+                )),
+            ),
+            Message::Entry(message) => FilterCommitments::retain(
+                &commitments.entry,
+                commitment,
+                self.entry.get_filters(message),
+            ),
+            Message::Block(message) => FilterCommitments::retain(
+                &commitments.blocks,
+                commitment,
+                self.blocks.get_filters(message),
+            ),
+            Message::BlockMeta(message) => FilterCommitments::retain(
+                &commitments.blocks_meta,
+                commitment,
+                self.blocks_meta.get_filters(message),
             ),
-            Message::Entry(message) => self.entry.get_filters(message),
-            Message::Block(message) => self.blocks.get_filters(message),
-            Message::BlockMeta(message) => self.blocks_meta.get_filters(message),
         }
     }
 
@@ -148,33 +282,611 @@ impl Filter {
         &'a self,
         message: &'a Message,
         commitment: Option<CommitmentLevel>,
-    ) -> Box<dyn Iterator<Item = SubscribeUpdate> + Send + 'a> {
+        cache: &'a MessagesEncodeCache,
+    ) -> Box<dyn Iterator<Item = FilteredUpdate> + Send + 'a> {
         Box::new(
             self.get_filters(message, commitment)
-                .filter_map(|(filters, message)| {
-                    if filters.is_empty() {
-                        None
-                    } else {
-                        Some(SubscribeUpdate {
+                .filter(|(filters, _message_ref)| !filters.is_empty())
+                .flat_map(move |(filters, message_ref)| {
+                    self.group_by_data_slice(filters, &message_ref)
+                        .into_iter()
+                        .map(move |(filters, accounts_data_slice)| FilteredUpdate {
                             filters,
-                            update_oneof: Some(message.to_proto(&self.accounts_data_slice)),
+                            commitment: commitment.map(|commitment| commitment as i32),
+                            slot: Some(message.get_slot()),
+                            update_oneof: FilteredUpdateOneof::Encoded(cache.get(
+                                message,
+                                &message_ref,
+                                accounts_data_slice,
+                            )),
                         })
-                    }
                 }),
         )
     }
 
-    pub fn get_pong_msg(&self) -> Option<SubscribeUpdate> {
-        self.ping.map(|id| SubscribeUpdate {
-            filters: vec![],
-            update_oneof: Some(UpdateOneof::Pong(SubscribeUpdatePong { id })),
+    // filters with different data slices receive separate updates
+    fn group_by_data_slice(
+        &self,
+        filters: Vec<String>,
+        message: &MessageRef<'_>,
+    ) -> Vec<(Vec<String>, &[FilterAccountsDataSlice])> {
+        if !matches!(message, MessageRef::Account(_)) || self.accounts.data_slice.is_empty() {
+            return vec![(filters, &self.accounts_data_slice)];
+        }
+
+        let mut groups: Vec<(Vec<String>, &[FilterAccountsDataSlice])> = vec![];
+        for name in filters {
+            let data_slice = self
+                .accounts
+                .data_slice
+                .get(&name)
+                .map(Vec::as_slice)
+                .unwrap_or(&self.accounts_data_slice);
+            match groups
+                .iter_mut()
+                .find(|(_filters, slice)| *slice == data_slice)
+            {
+                Some((filters, _slice)) => filters.push(name),
+                None => groups.push((vec![name], data_slice)),
+            }
+        }
+        groups
+    }
+
+    pub fn get_batch_filters_msg(&self) -> Option<FilteredUpdate> {
+        self.batch.as_ref().map(|filters| {
+            FilteredUpdate::new(
+                vec![],
+                None,
+                UpdateOneof::BatchFilters(SubscribeUpdateBatchFilters {
+                    filters: filters.clone(),
+                }),
+            )
+        })
+    }
+
+    // pack updates matched in one broadcast batch into one message
+    pub fn get_batch(
+        &self,
+        updates: Vec<FilteredUpdate>,
+        commitment: CommitmentLevel,
+    ) -> Vec<FilteredUpdate> {
+        let Some(batch_filters) = self.batch.as_ref() else {
+            return updates;
+        };
+
+        let mut messages = vec![];
+        let mut filters: Vec<(String, u64)> = vec![];
+        let mut slot = None;
+        let mut items = vec![];
+        for update in updates {
+            let message = match update.update_oneof {
+                FilteredUpdateOneof::Encoded(message) => message,
+                update_oneof => {
+                    messages.push(FilteredUpdate {
+                        update_oneof,
+                        ..update
+                    });
+                    continue;
+                }
+            };
+
+            slot = slot.max(update.slot);
+            let ids = update
+                .filters
+                .iter()
+                .filter_map(|name| batch_filters.binary_search(name).ok())
+                .map(|id| id as u32)
+                .collect();
+            for name in update.filters {
+                match filters.iter_mut().find(|(filter, _count)| *filter == name) {
+                    Some((_name, count)) => *count += 1,
+                    None => filters.push((name, 1)),
+                }
+            }
+            items.push((ids, message));
+        }
+
+        if !items.is_empty() {
+            // filter names are replaced with ids in batch items
+            messages.push(FilteredUpdate {
+                filters: vec![],
+                commitment: Some(commitment as i32),
+                slot,
+                update_oneof: FilteredUpdateOneof::Batch { filters, items },
+            });
+        }
+        messages
+    }
+
+    pub fn get_pong_msg(&self) -> Option<FilteredUpdate> {
+        self.ping.map(|id| {
+            FilteredUpdate::new(vec![], None, UpdateOneof::Pong(SubscribeUpdatePong { id }))
         })
     }
+
+    // accounts and transactions sent at processed commitment level from confirmed / finalized messages,
+    // one update per slot
+    pub fn get_commitment_upgrades<'a>(
+        &self,
+        messages: impl IntoIterator<Item = &'a Arc<Message>>,
+        commitment: CommitmentLevel,
+    ) -> Vec<FilteredUpdate> {
+        if !self.commitment_upgrades || commitment == CommitmentLevel::Processed {
+            return vec![];
+        }
+
+        let mut slots: BTreeMap<u64, (Vec<Vec<u8>>, Vec<_>)> = BTreeMap::new();
+        for message in messages {
+            if !matches!(
+                message.as_ref(),
+                Message::Account(_) | Message::Transaction(_)
+            ) || self
+                .get_filters(message, Some(CommitmentLevel::Processed))
+                .all(|(filters, _message)| filters.is_empty())
+            {
+                continue;
+            }
+
+            let (signatures, accounts) = slots.entry(message.get_slot()).or_default();
+            match message.as_ref() {
+                Message::Account(message) => {
+                    accounts.push(SubscribeUpdateCommitmentUpgradeAccount {
+                        pubkey: message.account.pubkey.as_ref().into(),
+                        write_version: message.account.write_version,
+                    });
+                }
+                Message::Transaction(message) => {
+                    signatures.push(message.transaction.signature.as_ref().into());
+                }
+                _ => {}
+            }
+        }
+
+        slots
+            .into_iter()
+            .map(|(slot, (signatures, accounts))| FilteredUpdate {
+                filters: vec![],
+                commitment: Some(commitment as i32),
+                slot: Some(slot),
+                update_oneof: FilteredUpdateOneof::Update(UpdateOneof::CommitmentUpgrade(
+                    SubscribeUpdateCommitmentUpgrade {
+                        slot,
+                        status: commitment as i32,
+                        signatures,
+                        accounts,
+                    },
+                )),
+            })
+            .collect()
+    }
+}
+
+// pubkeys required by filters, `None` if any account or transaction can match
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct FilterIndexKeys {
+    account: Option<HashSet<Pubkey>>,
+    owner: Option<HashSet<Pubkey>>,
+    transaction: Option<HashSet<Pubkey>>,
+    block_account: Option<HashSet<Pubkey>>,
+}
+
+impl FilterIndexKeys {
+    fn new(filter: &Filter) -> Self {
+        let accounts = &filter.accounts;
+        let mut account = Some(HashSet::new());
+        let mut owner = Some(HashSet::new());
+        for (name, _filter) in accounts.filters.iter() {
+            let (keys, map) = if accounts.account_required.contains(name) {
+                (&mut account, &accounts.account)
+            } else if accounts.owner_required.contains(name) {
+                (&mut owner, &accounts.owner)
+            } else {
+                account = None;
+                owner = None;
+                break;
+            };
+            if let Some(keys) = keys {
+                keys.extend(
+                    map.iter()
+                        .filter(|(_pubkey, names)| names.contains(name))
+                        .map(|(pubkey, _names)| *pubkey),
+                );
+            }
+        }
+
+        let mut transaction = Some(HashSet::new());
+        for inner in filter
+            .transactions
+            .filters
+            .values()
+            .chain(filter.transactions_status.filters.values())
+        {
+            // every list requires at least one of pubkeys in transaction account keys
+            let Some(pubkeys) = [
+                &inner.account_include,
+                &inner.account_include_writable,
+                &inner.account_include_signer,
+                &inner.account_required,
+                &inner.account_required_writable,
+                &inner.account_required_signer,
+                &inner.program_invoked,
+            ]
+            .into_iter()
+            .filter(|pubkeys| !pubkeys.is_empty())
+            .min_by_key(|pubkeys| pubkeys.len()) else {
+                transaction = None;
+                break;
+            };
+            if let Some(transaction) = transaction.as_mut() {
+                transaction.extend(pubkeys.iter().copied());
+            }
+        }
+
+        let mut block_account = Some(HashSet::new());
+        for inner in filter.blocks.filters.values() {
+            if inner.include_accounts != Some(true) {
+                continue;
+            }
+            if inner.account_include.is_empty() {
+                block_account = None;
+                break;
+            }
+            if let Some(block_account) = block_account.as_mut() {
+                block_account.extend(inner.account_include.iter().copied());
+            }
+        }
+
+        Self {
+            account,
+            owner,
+            transaction,
+            block_account,
+        }
+    }
+}
+
+/// Index of pubkeys required by account and transaction filters of all clients,
+/// used as pre-filter: messages are checked by full filters only for clients found in the index
+#[derive(Debug, Default)]
+pub struct FiltersIndex {
+    inner: RwLock<FiltersIndexInner>,
+    interest: ArcSwap<FiltersIndexInterest>, // read by plugin on every account update without locks
+    accounts_all: bool, // account updates are stored for replay with `from_slot`
+}
+
+// immutable snapshot of the index for accounts, replaced on every update
+#[derive(Debug, Default)]
+struct FiltersIndexInterest {
+    version: u64,
+    any: bool,
+    accounts: HashSet<Pubkey>,
+    owners: HashSet<Pubkey>,
+}
+
+impl FiltersIndexInterest {
+    fn new(inner: &FiltersIndexInner) -> Self {
+        let any = !inner.accounts_any.is_empty() || !inner.blocks_accounts_any.is_empty();
+        Self {
+            version: inner.version,
+            any,
+            accounts: if any {
+                HashSet::new()
+            } else {
+                inner
+                    .account
+                    .keys()
+                    .chain(inner.block_account.keys())
+                    .copied()
+                    .collect()
+            },
+            owners: if any {
+                HashSet::new()
+            } else {
+                inner.owner.keys().copied().collect()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FiltersIndexInner {
+    version: u64,
+    clients: HashMap<usize, FilterIndexKeys>,
+    accounts_any: HashSet<usize>,
+    account: HashMap<Pubkey, HashSet<usize>>,
+    owner: HashMap<Pubkey, HashSet<usize>>,
+    transactions_any: HashSet<usize>,
+    transaction: HashMap<Pubkey, HashSet<usize>>,
+    blocks_accounts_any: HashSet<usize>,
+    block_account: HashMap<Pubkey, HashSet<usize>>,
+}
+
+impl FiltersIndexInner {
+    fn insert(&mut self, id: usize, keys: &FilterIndexKeys) {
+        match &keys.account {
+            Some(pubkeys) => Self::insert_keys(&mut self.account, id, pubkeys),
+            None => {
+                self.accounts_any.insert(id);
+            }
+        }
+        if let Some(pubkeys) = &keys.owner {
+            Self::insert_keys(&mut self.owner, id, pubkeys);
+        }
+        match &keys.transaction {
+            Some(pubkeys) => Self::insert_keys(&mut self.transaction, id, pubkeys),
+            None => {
+                self.transactions_any.insert(id);
+            }
+        }
+        match &keys.block_account {
+            Some(pubkeys) => Self::insert_keys(&mut self.block_account, id, pubkeys),
+            None => {
+                self.blocks_accounts_any.insert(id);
+            }
+        }
+    }
+
+    fn insert_keys(
+        map: &mut HashMap<Pubkey, HashSet<usize>>,
+        id: usize,
+        pubkeys: &HashSet<Pubkey>,
+    ) {
+        for pubkey in pubkeys {
+            map.entry(*pubkey).or_default().insert(id);
+        }
+    }
+
+    fn remove(&mut self, id: usize, keys: &FilterIndexKeys) {
+        self.accounts_any.remove(&id);
+        self.transactions_any.remove(&id);
+        self.blocks_accounts_any.remove(&id);
+        for (map, pubkeys) in [
+            (&mut self.account, &keys.account),
+            (&mut self.owner, &keys.owner),
+            (&mut self.transaction, &keys.transaction),
+            (&mut self.block_account, &keys.block_account),
+        ] {
+            for pubkey in pubkeys.iter().flatten() {
+                if let Some(ids) = map.get_mut(pubkey) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        map.remove(pubkey);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FiltersIndex {
+    pub fn new(accounts_all: bool) -> Self {
+        Self {
+            accounts_all,
+            ..Default::default()
+        }
+    }
+
+    /// Replace filter of the client (or remove it with `None`), returns index version
+    pub fn update(&self, id: usize, filter: Option<&Filter>) -> u64 {
+        let keys = filter.map(FilterIndexKeys::new);
+        let version = {
+            let mut inner = self
+                .inner
+                .write()
+                .unwrap_or_else(|error| error.into_inner());
+            if let Some(keys) = inner.clients.remove(&id) {
+                inner.remove(id, &keys);
+            }
+            if let Some(keys) = keys {
+                inner.insert(id, &keys);
+                inner.clients.insert(id, keys);
+            }
+            inner.version += 1;
+            inner.version
+        };
+
+        // snapshot is built under read lock, concurrent update can publish newer version first
+        let interest = {
+            let inner = self.inner.read().unwrap_or_else(|error| error.into_inner());
+            Arc::new(FiltersIndexInterest::new(&inner))
+        };
+        self.interest.rcu(|current| {
+            if current.version > interest.version {
+                Arc::clone(current)
+            } else {
+                Arc::clone(&interest)
+            }
+        });
+
+        version
+    }
+
+    /// Check that account update can be matched by filter of any client, including blocks
+    pub fn is_account_interesting(&self, pubkey: &Pubkey, owner: &Pubkey) -> bool {
+        if self.accounts_all {
+            return true;
+        }
+        let interest = self.interest.load();
+        interest.any || interest.accounts.contains(pubkey) || interest.owners.contains(owner)
+    }
+
+    pub fn get_matches(&self, messages: &[Arc<Message>]) -> FiltersIndexMatches {
+        let inner = self.inner.read().unwrap_or_else(|error| error.into_inner());
+        let clients = messages
+            .iter()
+            .map(|message| {
+                let (kind, mut ids) = match message.as_ref() {
+                    Message::Account(message) => (
+                        FiltersIndexKind::Account,
+                        inner
+                            .account
+                            .get(&message.account.pubkey)
+                            .into_iter()
+                            .flatten()
+                            .chain(
+                                inner
+                                    .owner
+                                    .get(&message.account.owner)
+                                    .into_iter()
+                                    .flatten(),
+                            )
+                            .copied()
+                            .collect::<Vec<_>>(),
+                    ),
+                    Message::Transaction(message) => (
+                        FiltersIndexKind::Transaction,
+                        message
+                            .transaction
+                            .transaction
+                            .message()
+                            .account_keys()
+                            .iter()
+                            .filter_map(|pubkey| inner.transaction.get(pubkey))
+                            .flatten()
+                            .copied()
+                            .collect::<Vec<_>>(),
+                    ),
+                    _ => return None,
+                };
+                ids.sort_unstable();
+                ids.dedup();
+                Some((kind, ids))
+            })
+            .collect();
+        FiltersIndexMatches {
+            version: inner.version,
+            accounts_any: Self::get_sorted(&inner.accounts_any),
+            transactions_any: Self::get_sorted(&inner.transactions_any),
+            clients,
+        }
+    }
+
+    fn get_sorted(ids: &HashSet<usize>) -> Vec<usize> {
+        let mut ids = ids.iter().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FiltersIndexKind {
+    Account,
+    Transaction,
+}
+
+/// Clients which filters can match messages of one broadcasted batch
+#[derive(Debug, Default)]
+pub struct FiltersIndexMatches {
+    version: u64,
+    // clients with filters without required pubkeys, not repeated for every message
+    accounts_any: Vec<usize>,
+    transactions_any: Vec<usize>,
+    clients: Vec<Option<(FiltersIndexKind, Vec<usize>)>>, // None for messages without index
+}
+
+impl FiltersIndexMatches {
+    // filter registered after matches were created is checked against every message
+    pub fn is_match(&self, index: usize, id: usize, version: u64) -> bool {
+        if self.version < version {
+            return true;
+        }
+        let (any, ids) = match self.clients.get(index) {
+            Some(Some((FiltersIndexKind::Account, ids))) => (&self.accounts_any, ids),
+            Some(Some((FiltersIndexKind::Transaction, ids))) => (&self.transactions_any, ids),
+            _ => return true,
+        };
+        any.binary_search(&id).is_ok() || ids.binary_search(&id).is_ok()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct FilterCommitments {
+    accounts: HashMap<String, Vec<CommitmentLevel>>,
+    slots: HashMap<String, Vec<CommitmentLevel>>,
+    transactions: HashMap<String, Vec<CommitmentLevel>>,
+    transactions_status: HashMap<String, Vec<CommitmentLevel>>,
+    entry: HashMap<String, Vec<CommitmentLevel>>,
+    blocks: HashMap<String, Vec<CommitmentLevel>>,
+    blocks_meta: HashMap<String, Vec<CommitmentLevel>>,
+    levels: Vec<CommitmentLevel>,
+}
+
+impl FilterCommitments {
+    fn new(config: &SubscribeRequest, default: &[CommitmentLevel]) -> anyhow::Result<Self> {
+        let mut this = Self {
+            accounts: Self::decode(&config.accounts, |filter| filter.commitment, default)?,
+            slots: Self::decode(&config.slots, |filter| filter.commitment, default)?,
+            transactions: Self::decode(&config.transactions, |filter| filter.commitment, default)?,
+            transactions_status: Self::decode(
+                &config.transactions_status,
+                |filter| filter.commitment,
+                default,
+            )?,
+            entry: Self::decode(&config.entry, |filter| filter.commitment, default)?,
+            blocks: Self::decode(&config.blocks, |filter| filter.commitment, default)?,
+            blocks_meta: Self::decode(&config.blocks_meta, |filter| filter.commitment, default)?,
+            levels: vec![],
+        };
+
+        this.levels = [
+            &this.accounts,
+            &this.slots,
+            &this.transactions,
+            &this.transactions_status,
+            &this.entry,
+            &this.blocks,
+            &this.blocks_meta,
+        ]
+        .into_iter()
+        .flat_map(|commitments| commitments.values().flatten().copied())
+        .collect();
+        this.levels.sort();
+        this.levels.dedup();
+
+        Ok(this)
+    }
+
+    fn decode<T>(
+        configs: &HashMap<String, T>,
+        get_commitment: impl Fn(&T) -> Option<i32>,
+        default: &[CommitmentLevel],
+    ) -> anyhow::Result<HashMap<String, Vec<CommitmentLevel>>> {
+        configs
+            .iter()
+            .map(|(name, filter)| {
+                let commitments = match get_commitment(filter) {
+                    Some(commitment) => vec![Filter::decode_commitment(Some(commitment))?],
+                    None => default.to_vec(),
+                };
+                Ok((name.clone(), commitments))
+            })
+            .collect()
+    }
+
+    fn retain<'a>(
+        commitments: &'a HashMap<String, Vec<CommitmentLevel>>,
+        commitment: Option<CommitmentLevel>,
+        updates: Box<dyn Iterator<Item = (Vec<String>, MessageRef<'a>)> + Send + 'a>,
+    ) -> Box<dyn Iterator<Item = (Vec<String>, MessageRef<'a>)> + Send + 'a> {
+        match commitment {
+            Some(commitment) => Box::new(updates.map(move |(mut filters, message)| {
+                filters.retain(|name| {
+                    commitments
+                        .get(name)
+                        .is_some_and(|levels| levels.contains(&commitment))
+                });
+                (filters, message)
+            })),
+            None => updates,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 struct FilterAccounts {
     filters: Vec<(String, FilterAccountsData)>,
+    data_slice: HashMap<String, Vec<FilterAccountsDataSlice>>,
     account: HashMap<Pubkey, HashSet<String>>,
     account_required: HashSet<String>,
     owner: HashMap<Pubkey, HashSet<String>>,
@@ -211,8 +923,17 @@ impl FilterAccounts {
                 Filter::decode_pubkeys(&filter.owner, &limit.owner_reject),
             )?;
 
-            this.filters
-                .push((name.clone(), FilterAccountsData::new(&filter.filters)?));
+            this.filters.push((
+                name.clone(),
+                FilterAccountsData::new(&filter.filters, limit)?,
+            ));
+
+            if !filter.data_slice.is_empty() {
+                this.data_slice.insert(
+                    name.clone(),
+                    FilterAccountsDataSlice::create(&filter.data_slice)?,
+                );
+            }
         }
         Ok(this)
     }
@@ -243,7 +964,11 @@ impl FilterAccounts {
         let mut filter = FilterAccountsMatch::new(self);
         filter.match_account(&message.account.pubkey);
         filter.match_owner(&message.account.owner);
-        filter.match_data(&message.account.data);
+        filter.match_data_lamports(
+            &message.account.owner,
+            &message.account.data,
+            message.account.lamports,
+        );
         Box::new(std::iter::once((
             filter.get_filters(),
             MessageRef::Account(message),
@@ -253,23 +978,57 @@ impl FilterAccounts {
 
 #[derive(Debug, Default, Clone)]
 struct FilterAccountsData {
-    memcmp: Vec<(usize, Vec<u8>)>,
-    datasize: Option<usize>,
+    memcmp: Vec<FilterAccountsMemcmp>,
+    datasize: Option<FilterAccountsDatasize>,
     token_account_state: bool,
+    lamports: Vec<FilterAccountsLamports>,
+    token_mint: Option<Pubkey>,
+    token_owner: Option<Pubkey>,
+    any_of: Vec<Vec<FilterAccountsData>>,
+    not: Vec<FilterAccountsData>,
 }
 
 impl FilterAccountsData {
-    fn new(filters: &[SubscribeRequestFilterAccountsFilter]) -> anyhow::Result<Self> {
-        const MAX_FILTERS: usize = 4;
-        const MAX_DATA_SIZE: usize = 128;
-        const MAX_DATA_BASE58_SIZE: usize = 175;
-        const MAX_DATA_BASE64_SIZE: usize = 172;
+    fn new(
+        filters: &[SubscribeRequestFilterAccountsFilter],
+        limit: &ConfigGrpcFiltersAccounts,
+    ) -> anyhow::Result<Self> {
+        let filters_count = Self::count(filters, 0, limit.nested_max)?;
+        anyhow::ensure!(
+            filters_count <= limit.filters_max,
+            "Too many filters provided; max {}",
+            limit.filters_max
+        );
+        Self::create(filters)
+    }
 
+    // nested filters from `any_of` and `not` are counted too, depth is checked before recursion
+    fn count(
+        filters: &[SubscribeRequestFilterAccountsFilter],
+        depth: usize,
+        nested_max: usize,
+    ) -> anyhow::Result<usize> {
         anyhow::ensure!(
-            filters.len() <= MAX_FILTERS,
-            "Too many filters provided; max {MAX_FILTERS}"
+            depth <= nested_max,
+            "Max depth of nested any_of / not filters reached, only {} allowed",
+            nested_max
         );
+        let mut count = 0;
+        for filter in filters {
+            count += 1 + match &filter.filter {
+                Some(AccountsFilterDataOneof::AnyOf(any_of)) => {
+                    Self::count(&any_of.filters, depth + 1, nested_max)?
+                }
+                Some(AccountsFilterDataOneof::Not(not)) => {
+                    Self::count(&not.filters, depth + 1, nested_max)?
+                }
+                _ => 0,
+            };
+        }
+        Ok(count)
+    }
 
+    fn create(filters: &[SubscribeRequestFilterAccountsFilter]) -> anyhow::Result<Self> {
         let mut this = Self::default();
         for filter in filters {
             match &filter.filter {
@@ -277,25 +1036,43 @@ impl FilterAccountsData {
                     let data = match &memcmp.data {
                         Some(AccountsFilterMemcmpOneof::Bytes(data)) => data.clone(),
                         Some(AccountsFilterMemcmpOneof::Base58(data)) => {
-                            anyhow::ensure!(data.len() <= MAX_DATA_BASE58_SIZE, "data too large");
-                            bs58::decode(data)
-                                .into_vec()
-                                .map_err(|_| anyhow::anyhow!("invalid base58"))?
+                            Filter::decode_data_base58(data)?
                         }
                         Some(AccountsFilterMemcmpOneof::Base64(data)) => {
-                            anyhow::ensure!(data.len() <= MAX_DATA_BASE64_SIZE, "data too large");
-                            base64_engine
-                                .decode(data)
-                                .map_err(|_| anyhow::anyhow!("invalid base64"))?
+                            Filter::decode_data_base64(data)?
                         }
                         None => anyhow::bail!("data for memcmp should be defined"),
                     };
-                    anyhow::ensure!(data.len() <= MAX_DATA_SIZE, "data too large");
-                    this.memcmp.push((memcmp.offset as usize, data));
+                    anyhow::ensure!(data.len() <= Filter::MAX_DATA_SIZE, "data too large");
+                    this.memcmp.push(FilterAccountsMemcmp::new(
+                        memcmp.offset as usize,
+                        data,
+                        memcmp.mask.clone(),
+                    )?);
                 }
                 Some(AccountsFilterDataOneof::Datasize(datasize)) => {
+                    let datasize = *datasize as usize;
+                    anyhow::ensure!(
+                        this.datasize
+                            .replace(FilterAccountsDatasize {
+                                min: datasize,
+                                max: datasize,
+                            })
+                            .is_none(),
+                        "datasize used more than once",
+                    );
+                }
+                Some(AccountsFilterDataOneof::DatasizeRange(range)) => {
+                    let datasize = FilterAccountsDatasize {
+                        min: range.min.unwrap_or(0) as usize,
+                        max: range.max.map(|max| max as usize).unwrap_or(usize::MAX),
+                    };
+                    anyhow::ensure!(
+                        datasize.min <= datasize.max,
+                        "datasize_range min should not be greater than max"
+                    );
                     anyhow::ensure!(
-                        this.datasize.replace(*datasize as usize).is_none(),
+                        this.datasize.replace(datasize).is_none(),
                         "datasize used more than once",
                     );
                 }
@@ -303,6 +1080,42 @@ impl FilterAccountsData {
                     anyhow::ensure!(value, "token_account_state only allowed to be true");
                     this.token_account_state = true;
                 }
+                Some(AccountsFilterDataOneof::Lamports(lamports)) => {
+                    this.lamports.push(lamports.try_into()?);
+                }
+                Some(AccountsFilterDataOneof::TokenMint(token_mint)) => {
+                    let token_mint = Pubkey::from_str(token_mint)
+                        .map_err(|error| anyhow::anyhow!("invalid token_mint: {error}"))?;
+                    anyhow::ensure!(
+                        this.token_mint.replace(token_mint).is_none(),
+                        "token_mint used more than once",
+                    );
+                }
+                Some(AccountsFilterDataOneof::TokenOwner(token_owner)) => {
+                    let token_owner = Pubkey::from_str(token_owner)
+                        .map_err(|error| anyhow::anyhow!("invalid token_owner: {error}"))?;
+                    anyhow::ensure!(
+                        this.token_owner.replace(token_owner).is_none(),
+                        "token_owner used more than once",
+                    );
+                }
+                Some(AccountsFilterDataOneof::AnyOf(any_of)) => {
+                    anyhow::ensure!(
+                        !any_of.filters.is_empty(),
+                        "filters for any_of should be defined"
+                    );
+                    this.any_of.push(
+                        any_of
+                            .filters
+                            .iter()
+                            .map(|filter| Self::create(std::slice::from_ref(filter)))
+                            .collect::<anyhow::Result<_>>()?,
+                    );
+                }
+                Some(AccountsFilterDataOneof::Not(not)) => {
+                    anyhow::ensure!(!not.filters.is_empty(), "filters for not should be defined");
+                    this.not.push(Self::create(&not.filters)?);
+                }
                 None => {
                     anyhow::bail!("filter should be defined");
                 }
@@ -312,44 +1125,165 @@ impl FilterAccountsData {
     }
 
     fn is_empty(&self) -> bool {
-        self.memcmp.is_empty() && self.datasize.is_none() && !self.token_account_state
+        self.memcmp.is_empty()
+            && self.datasize.is_none()
+            && !self.token_account_state
+            && self.lamports.is_empty()
+            && self.token_mint.is_none()
+            && self.token_owner.is_none()
+            && self.any_of.is_empty()
+            && self.not.is_empty()
     }
 
-    fn is_match(&self, data: &[u8]) -> bool {
-        if matches!(self.datasize, Some(datasize) if data.len() != datasize) {
+    fn is_match(&self, owner: &Pubkey, data: &[u8], lamports: u64) -> bool {
+        if !self.lamports.iter().all(|filter| filter.is_match(lamports)) {
+            return false;
+        }
+        if matches!(self.datasize, Some(datasize) if !datasize.is_match(data.len())) {
             return false;
         }
         if self.token_account_state && !TokenAccount::valid_account_data(data) {
             return false;
         }
-        for (offset, bytes) in self.memcmp.iter() {
-            if data.len() < *offset + bytes.len() {
+        if self.token_mint.is_some() || self.token_owner.is_some() {
+            // data of accounts owned by other programs can mimic token account layout
+            if spl_token_2022::check_spl_token_program_account(owner).is_err() {
                 return false;
             }
-            let data = &data[*offset..*offset + bytes.len()];
-            if data != bytes {
+            // handles both SPL Token and Token-2022 layouts, including extensions
+            let account = match StateWithExtensions::<TokenAccount>::unpack(data) {
+                Ok(account) => account,
+                Err(_error) => return false,
+            };
+            if matches!(self.token_mint, Some(mint) if mint != account.base.mint) {
+                return false;
+            }
+            if matches!(self.token_owner, Some(owner) if owner != account.base.owner) {
                 return false;
             }
         }
+        if !self.memcmp.iter().all(|memcmp| memcmp.is_match(data)) {
+            return false;
+        }
+        if !self.any_of.iter().all(|filters| {
+            filters
+                .iter()
+                .any(|filter| filter.is_match(owner, data, lamports))
+        }) {
+            return false;
+        }
+        if self
+            .not
+            .iter()
+            .any(|filter| filter.is_match(owner, data, lamports))
+        {
+            return false;
+        }
         true
     }
 }
 
-#[derive(Debug)]
-pub struct FilterAccountsMatch<'a> {
-    filter: &'a FilterAccounts,
-    account: HashSet<&'a str>,
-    owner: HashSet<&'a str>,
-    data: HashSet<&'a str>,
+#[derive(Debug, Clone)]
+struct FilterAccountsMemcmp {
+    offset: usize,
+    data: Vec<u8>,
+    mask: Option<Vec<u8>>,
 }
 
-impl<'a> FilterAccountsMatch<'a> {
-    fn new(filter: &'a FilterAccounts) -> Self {
-        Self {
-            filter,
-            account: Default::default(),
-            owner: Default::default(),
-            data: Default::default(),
+impl FilterAccountsMemcmp {
+    fn new(offset: usize, mut data: Vec<u8>, mask: Option<Vec<u8>>) -> anyhow::Result<Self> {
+        if let Some(mask) = &mask {
+            anyhow::ensure!(
+                mask.len() == data.len(),
+                "mask for memcmp should have the same length as data"
+            );
+            for (byte, mask) in data.iter_mut().zip(mask.iter()) {
+                *byte &= mask;
+            }
+        }
+        Ok(Self { offset, data, mask })
+    }
+
+    fn is_match(&self, data: &[u8]) -> bool {
+        let data = match self
+            .offset
+            .checked_add(self.data.len())
+            .and_then(|end| data.get(self.offset..end))
+        {
+            Some(data) => data,
+            None => return false,
+        };
+        match &self.mask {
+            Some(mask) => data
+                .iter()
+                .zip(mask.iter())
+                .map(|(byte, mask)| byte & mask)
+                .eq(self.data.iter().copied()),
+            None => data == self.data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FilterAccountsDatasize {
+    min: usize,
+    max: usize,
+}
+
+impl FilterAccountsDatasize {
+    const fn is_match(self, datasize: usize) -> bool {
+        self.min <= datasize && datasize <= self.max
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FilterAccountsLamports {
+    Eq(u64),
+    Ne(u64),
+    Lt(u64),
+    Gt(u64),
+}
+
+impl TryFrom<&SubscribeRequestFilterAccountsFilterLamports> for FilterAccountsLamports {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &SubscribeRequestFilterAccountsFilterLamports) -> Result<Self, Self::Error> {
+        match value.cmp {
+            Some(AccountsFilterLamports::Eq(value)) => Ok(Self::Eq(value)),
+            Some(AccountsFilterLamports::Ne(value)) => Ok(Self::Ne(value)),
+            Some(AccountsFilterLamports::Lt(value)) => Ok(Self::Lt(value)),
+            Some(AccountsFilterLamports::Gt(value)) => Ok(Self::Gt(value)),
+            None => anyhow::bail!("cmp for lamports should be defined"),
+        }
+    }
+}
+
+impl FilterAccountsLamports {
+    const fn is_match(self, lamports: u64) -> bool {
+        match self {
+            Self::Eq(value) => lamports == value,
+            Self::Ne(value) => lamports != value,
+            Self::Lt(value) => lamports < value,
+            Self::Gt(value) => lamports > value,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FilterAccountsMatch<'a> {
+    filter: &'a FilterAccounts,
+    account: HashSet<&'a str>,
+    owner: HashSet<&'a str>,
+    data: HashSet<&'a str>,
+}
+
+impl<'a> FilterAccountsMatch<'a> {
+    fn new(filter: &'a FilterAccounts) -> Self {
+        Self {
+            filter,
+            account: Default::default(),
+            owner: Default::default(),
+            data: Default::default(),
         }
     }
 
@@ -369,9 +1303,9 @@ impl<'a> FilterAccountsMatch<'a> {
         Self::extend(&mut self.owner, &self.filter.owner, pubkey)
     }
 
-    pub fn match_data(&mut self, data: &[u8]) {
+    pub fn match_data_lamports(&mut self, owner: &Pubkey, data: &[u8], lamports: u64) {
         for (name, filter) in self.filter.filters.iter() {
-            if filter.is_match(data) {
+            if filter.is_match(owner, data, lamports) {
                 self.data.insert(name);
             }
         }
@@ -470,6 +1404,81 @@ pub struct FilterTransactionsInner {
     account_include: Vec<Pubkey>,
     account_exclude: Vec<Pubkey>,
     account_required: Vec<Pubkey>,
+    account_include_writable: Vec<Pubkey>,
+    account_include_signer: Vec<Pubkey>,
+    account_required_writable: Vec<Pubkey>,
+    account_required_signer: Vec<Pubkey>,
+    program_invoked: Vec<Pubkey>,
+    log_contains: Vec<String>,
+    instructions: Vec<FilterTransactionsInstruction>,
+    token_balance: Vec<FilterTransactionsTokenBalance>,
+}
+
+#[derive(Debug, Clone)]
+struct FilterTransactionsInstruction {
+    program_id: Pubkey,
+    data: Vec<u8>,
+}
+
+impl FilterTransactionsInstruction {
+    fn new(instruction: &SubscribeRequestFilterTransactionsInstruction) -> anyhow::Result<Self> {
+        let program_id = instruction
+            .program_id
+            .parse()
+            .map_err(|error| anyhow::anyhow!("invalid program_id: {error}"))?;
+        let data = match &instruction.data {
+            Some(TransactionsFilterInstructionDataOneof::Bytes(data)) => data.clone(),
+            Some(TransactionsFilterInstructionDataOneof::Base58(data)) => {
+                Filter::decode_data_base58(data)?
+            }
+            Some(TransactionsFilterInstructionDataOneof::Base64(data)) => {
+                Filter::decode_data_base64(data)?
+            }
+            None => anyhow::bail!("data for instruction should be defined"),
+        };
+        anyhow::ensure!(data.len() <= Filter::MAX_DATA_SIZE, "data too large");
+        Ok(Self { program_id, data })
+    }
+
+    fn is_match(&self, program_id: &Pubkey, data: &[u8]) -> bool {
+        &self.program_id == program_id && data.starts_with(&self.data)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FilterTransactionsTokenBalance {
+    mint: String,
+    owner: Vec<String>,
+}
+
+impl FilterTransactionsTokenBalance {
+    fn new(
+        token_balance: &SubscribeRequestFilterTransactionsTokenBalance,
+        limit: &ConfigGrpcFiltersTransactions,
+    ) -> anyhow::Result<Self> {
+        ConfigGrpcFilters::check_pubkey_max(
+            token_balance.owner.len(),
+            limit.token_balance_owner_max,
+        )?;
+
+        // balances in `TransactionStatusMeta` keep mint and owner as strings
+        let mint = Pubkey::from_str(&token_balance.mint)
+            .map_err(|error| anyhow::anyhow!("invalid mint: {error}"))?;
+        let mut owner = Filter::decode_pubkeys(&token_balance.owner, &HashSet::new())
+            .map(|pubkey| pubkey.map(|pubkey| pubkey.to_string()))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        owner.sort();
+
+        Ok(Self {
+            mint: mint.to_string(),
+            owner,
+        })
+    }
+
+    fn is_match(&self, balance: &TransactionTokenBalance) -> bool {
+        self.mint == balance.mint
+            && (self.owner.is_empty() || self.owner.binary_search(&balance.owner).is_ok())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -493,7 +1502,15 @@ impl FilterTransactions {
                     && filter.failed.is_none()
                     && filter.account_include.is_empty()
                     && filter.account_exclude.is_empty()
-                    && filter.account_required.is_empty(),
+                    && filter.account_required.is_empty()
+                    && filter.account_include_writable.is_empty()
+                    && filter.account_include_signer.is_empty()
+                    && filter.account_required_writable.is_empty()
+                    && filter.account_required_signer.is_empty()
+                    && filter.program_invoked.is_empty()
+                    && filter.log_contains.is_empty()
+                    && filter.instructions.is_empty()
+                    && filter.token_balance.is_empty(),
                 limit.any,
             )?;
             ConfigGrpcFilters::check_pubkey_max(
@@ -508,6 +1525,53 @@ impl FilterTransactions {
                 filter.account_required.len(),
                 limit.account_required_max,
             )?;
+            ConfigGrpcFilters::check_pubkey_max(
+                filter.account_include_writable.len(),
+                limit.account_include_max,
+            )?;
+            ConfigGrpcFilters::check_pubkey_max(
+                filter.account_include_signer.len(),
+                limit.account_include_max,
+            )?;
+            ConfigGrpcFilters::check_pubkey_max(
+                filter.account_required_writable.len(),
+                limit.account_required_max,
+            )?;
+            ConfigGrpcFilters::check_pubkey_max(
+                filter.account_required_signer.len(),
+                limit.account_required_max,
+            )?;
+            ConfigGrpcFilters::check_pubkey_max(
+                filter.program_invoked.len(),
+                limit.program_invoked_max,
+            )?;
+            anyhow::ensure!(
+                filter.log_contains.len() <= limit.log_contains_max,
+                "Max amount of log_contains reached, only {} allowed",
+                limit.log_contains_max
+            );
+            anyhow::ensure!(
+                filter.log_contains.iter().all(|value| !value.is_empty()),
+                "log_contains value should not be empty"
+            );
+            anyhow::ensure!(
+                filter
+                    .log_contains
+                    .iter()
+                    .all(|value| value.len() <= limit.log_contains_length_max),
+                "Max length of log_contains value reached, only {} allowed",
+                limit.log_contains_length_max
+            );
+            anyhow::ensure!(
+                filter.instructions.len() <= limit.instructions_max,
+                "Max amount of instructions reached, only {} allowed",
+                limit.instructions_max
+            );
+            anyhow::ensure!(
+                filter.token_balance.len() <= limit.token_balance_max,
+                "Max amount of token_balance reached, only {} allowed",
+                limit.token_balance_max
+            );
 
             filters.insert(
                 name.clone(),
@@ -535,6 +1599,39 @@ impl FilterTransactions {
                         &filter.account_required,
                         &HashSet::new(),
                     )?,
+                    account_include_writable: Filter::decode_pubkeys_into_vec(
+                        &filter.account_include_writable,
+                        &limit.account_include_reject,
+                    )?,
+                    account_include_signer: Filter::decode_pubkeys_into_vec(
+                        &filter.account_include_signer,
+                        &limit.account_include_reject,
+                    )?,
+                    account_required_writable: Filter::decode_pubkeys_into_vec(
+                        &filter.account_required_writable,
+                        &HashSet::new(),
+                    )?,
+                    account_required_signer: Filter::decode_pubkeys_into_vec(
+                        &filter.account_required_signer,
+                        &HashSet::new(),
+                    )?,
+                    program_invoked: Filter::decode_pubkeys_into_vec(
+                        &filter.program_invoked,
+                        &HashSet::new(),
+                    )?,
+                    log_contains: filter.log_contains.clone(),
+                    instructions: filter
+                        .instructions
+                        .iter()
+                        .map(FilterTransactionsInstruction::new)
+                        .collect::<anyhow::Result<_>>()?,
+                    token_balance: filter
+                        .token_balance
+                        .iter()
+                        .map(|token_balance| {
+                            FilterTransactionsTokenBalance::new(token_balance, limit)
+                        })
+                        .collect::<anyhow::Result<_>>()?,
                 },
             );
         }
@@ -544,6 +1641,85 @@ impl FilterTransactions {
         })
     }
 
+    fn iter_account_keys(
+        message: &MessageTransaction,
+        is_match: fn(&SanitizedMessage, usize) -> bool,
+    ) -> impl Iterator<Item = &Pubkey> {
+        let sanitized_message = message.transaction.transaction.message();
+        sanitized_message
+            .account_keys()
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, pubkey)| is_match(sanitized_message, index).then_some(pubkey))
+    }
+
+    fn is_include<'a>(
+        account_include: &[Pubkey],
+        mut account_keys: impl Iterator<Item = &'a Pubkey>,
+    ) -> bool {
+        account_keys.any(|pubkey| account_include.binary_search(pubkey).is_ok())
+    }
+
+    fn is_required<'a>(
+        account_required: &[Pubkey],
+        account_keys: impl Iterator<Item = &'a Pubkey>,
+    ) -> bool {
+        let mut other: Vec<&Pubkey> = account_keys.collect();
+        if account_required.len() <= other.len() {
+            other.sort();
+            account_required
+                .iter()
+                .all(|pubkey| other.binary_search(&pubkey).is_ok())
+        } else {
+            false
+        }
+    }
+
+    fn is_token_balance_changed(
+        token_balance: &[FilterTransactionsTokenBalance],
+        meta: &TransactionStatusMeta,
+    ) -> bool {
+        let pre = meta.pre_token_balances.as_deref().unwrap_or_default();
+        let post = meta.post_token_balances.as_deref().unwrap_or_default();
+
+        // missed balance means that token account was created or closed
+        let get_amount = |balances: &[TransactionTokenBalance], account_index: u8| {
+            balances
+                .iter()
+                .find(|balance| balance.account_index == account_index)
+                .map(|balance| balance.ui_token_amount.amount.as_str())
+                .unwrap_or("0")
+        };
+
+        pre.iter()
+            .chain(post.iter())
+            .filter(|balance| token_balance.iter().any(|filter| filter.is_match(balance)))
+            .any(|balance| {
+                get_amount(pre, balance.account_index) != get_amount(post, balance.account_index)
+            })
+    }
+
+    fn iter_instructions(
+        message: &MessageTransaction,
+    ) -> impl Iterator<Item = &CompiledInstruction> {
+        message
+            .transaction
+            .transaction
+            .message()
+            .instructions()
+            .iter()
+            .chain(
+                message
+                    .transaction
+                    .meta
+                    .inner_instructions
+                    .iter()
+                    .flatten()
+                    .flat_map(|ixs| ixs.instructions.iter())
+                    .map(|ix| &ix.instruction),
+            )
+    }
+
     pub fn get_filters<'a>(
         &'a self,
         message: &'a MessageTransaction,
@@ -571,13 +1747,28 @@ impl FilterTransactions {
                 }
 
                 if !inner.account_include.is_empty()
-                    && message
-                        .transaction
-                        .transaction
-                        .message()
-                        .account_keys()
-                        .iter()
-                        .all(|pubkey| inner.account_include.binary_search(pubkey).is_err())
+                    && !Self::is_include(
+                        &inner.account_include,
+                        Self::iter_account_keys(message, |_message, _index| true),
+                    )
+                {
+                    return None;
+                }
+
+                if !inner.account_include_writable.is_empty()
+                    && !Self::is_include(
+                        &inner.account_include_writable,
+                        Self::iter_account_keys(message, SanitizedMessage::is_writable),
+                    )
+                {
+                    return None;
+                }
+
+                if !inner.account_include_signer.is_empty()
+                    && !Self::is_include(
+                        &inner.account_include_signer,
+                        Self::iter_account_keys(message, SanitizedMessage::is_signer),
+                    )
                 {
                     return None;
                 }
@@ -594,30 +1785,84 @@ impl FilterTransactions {
                     return None;
                 }
 
-                if !inner.account_required.is_empty() {
-                    let mut other: Vec<&Pubkey> = message
-                        .transaction
+                if !inner.account_required.is_empty()
+                    && !Self::is_required(
+                        &inner.account_required,
+                        Self::iter_account_keys(message, |_message, _index| true),
+                    )
+                {
+                    return None;
+                }
+
+                if !inner.account_required_writable.is_empty()
+                    && !Self::is_required(
+                        &inner.account_required_writable,
+                        Self::iter_account_keys(message, SanitizedMessage::is_writable),
+                    )
+                {
+                    return None;
+                }
+
+                if !inner.account_required_signer.is_empty()
+                    && !Self::is_required(
+                        &inner.account_required_signer,
+                        Self::iter_account_keys(message, SanitizedMessage::is_signer),
+                    )
+                {
+                    return None;
+                }
+
+                if !inner.program_invoked.is_empty() {
+                    let account_keys = message.transaction.transaction.message().account_keys();
+                    let is_invoked = Self::iter_instructions(message)
+                        .filter_map(|ix| account_keys.get(ix.program_id_index as usize))
+                        .any(|pubkey| inner.program_invoked.binary_search(pubkey).is_ok());
+
+                    if !is_invoked {
+                        return None;
+                    }
+                }
+
+                if !inner.log_contains.is_empty()
+                    && !message
                         .transaction
-                        .message()
-                        .account_keys()
+                        .meta
+                        .log_messages
                         .iter()
-                        .collect();
-
-                    let is_subset = if inner.account_required.len() <= other.len() {
-                        other.sort();
-                        inner
-                            .account_required
-                            .iter()
-                            .all(|pubkey| other.binary_search(&pubkey).is_ok())
-                    } else {
-                        false
-                    };
+                        .flatten()
+                        .any(|log| inner.log_contains.iter().any(|value| log.contains(value)))
+                {
+                    return None;
+                }
 
-                    if !is_subset {
+                if !inner.instructions.is_empty() {
+                    let account_keys = message.transaction.transaction.message().account_keys();
+                    let is_match = Self::iter_instructions(message).any(|ix| {
+                        account_keys
+                            .get(ix.program_id_index as usize)
+                            .map(|program_id| {
+                                inner
+                                    .instructions
+                                    .iter()
+                                    .any(|filter| filter.is_match(program_id, &ix.data))
+                            })
+                            .unwrap_or(false)
+                    });
+
+                    if !is_match {
                         return None;
                     }
                 }
 
+                if !inner.token_balance.is_empty()
+                    && !Self::is_token_balance_changed(
+                        &inner.token_balance,
+                        &message.transaction.meta,
+                    )
+                {
+                    return None;
+                }
+
                 Some(name.clone())
             })
             .collect();
@@ -817,7 +2062,7 @@ impl FilterBlocksMeta {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FilterAccountsDataSlice {
     pub start: usize,
     pub end: usize,
@@ -854,28 +2099,156 @@ impl FilterAccountsDataSlice {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAccountsCoalesce {
+    Slot,
+    Interval(Duration),
+}
+
+impl FilterAccountsCoalesce {
+    fn new(coalesce: Option<&SubscribeRequestAccountsCoalesce>) -> anyhow::Result<Option<Self>> {
+        Ok(match coalesce {
+            Some(SubscribeRequestAccountsCoalesce {
+                interval_ms: Some(interval_ms),
+            }) => {
+                anyhow::ensure!(*interval_ms > 0, "coalesce interval should be positive");
+                Some(Self::Interval(Duration::from_millis(*interval_ms)))
+            }
+            Some(SubscribeRequestAccountsCoalesce { interval_ms: None }) => Some(Self::Slot),
+            None => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterOverflow {
+    pub policy: OverflowPolicy,
+    pub rate_limit: Option<u64>,
+    pub sample_rate: u64,
+}
+
+impl Default for FilterOverflow {
+    fn default() -> Self {
+        Self {
+            policy: OverflowPolicy::Disconnect,
+            rate_limit: None,
+            sample_rate: Self::SAMPLE_RATE_DEFAULT,
+        }
+    }
+}
+
+impl FilterOverflow {
+    const SAMPLE_RATE_DEFAULT: u64 = 10;
+
+    fn new(overflow: Option<&SubscribeRequestOverflow>) -> anyhow::Result<Self> {
+        let overflow = match overflow {
+            Some(overflow) => overflow,
+            None => return Ok(Self::default()),
+        };
+
+        let policy = OverflowPolicy::try_from(overflow.policy).map_err(|_error| {
+            anyhow::anyhow!("failed to create OverflowPolicy from {:?}", overflow.policy)
+        })?;
+        if let Some(rate_limit) = overflow.rate_limit {
+            anyhow::ensure!(rate_limit > 0, "rate_limit should be positive");
+            anyhow::ensure!(
+                policy != OverflowPolicy::Disconnect,
+                "rate_limit is not allowed with disconnect policy"
+            );
+        }
+        let sample_rate = overflow.sample_rate.unwrap_or(Self::SAMPLE_RATE_DEFAULT);
+        anyhow::ensure!(sample_rate > 0, "sample_rate should be positive");
+
+        Ok(Self {
+            policy,
+            rate_limit: overflow.rate_limit,
+            sample_rate,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use {
         crate::{
+            codec::{FilteredUpdate, FilteredUpdateOneof, MessagesEncodeCache},
             config::ConfigGrpcFilters,
-            filters::Filter,
-            grpc::{Message, MessageRef, MessageTransaction, MessageTransactionInfo},
+            filters::{Filter, FilterAccountsCoalesce, FilterOverflow, FiltersIndex},
+            grpc::{
+                Message, MessageAccount, MessageAccountInfo, MessageRef, MessageTransaction,
+                MessageTransactionInfo,
+            },
         },
         solana_sdk::{
             hash::Hash,
+            instruction::CompiledInstruction,
             message::{v0::LoadedAddresses, Message as SolMessage, MessageHeader},
             pubkey::Pubkey,
             signer::{keypair::Keypair, Signer},
             transaction::{SanitizedTransaction, Transaction},
         },
-        solana_transaction_status::TransactionStatusMeta,
-        std::collections::HashMap,
-        yellowstone_grpc_proto::geyser::{
-            SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterTransactions,
+        solana_transaction_status::{
+            InnerInstruction, InnerInstructions, TransactionStatusMeta, TransactionTokenBalance,
+        },
+        spl_token_2022::{
+            extension::{
+                immutable_owner::ImmutableOwner, BaseStateWithExtensionsMut, ExtensionType,
+                StateWithExtensionsMut,
+            },
+            solana_program::program_pack::Pack,
+            state::{Account as TokenAccount, AccountState},
+        },
+        std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration},
+        yellowstone_grpc_proto::{
+            convert_from,
+            geyser::{
+                subscribe_request_filter_accounts_filter::Filter as AccountsFilterDataOneof,
+                subscribe_request_filter_accounts_filter_lamports::Cmp as AccountsFilterLamports,
+                subscribe_request_filter_accounts_filter_memcmp::Data as AccountsFilterMemcmpOneof,
+                subscribe_request_filter_transactions_instruction::Data as TransactionsFilterInstructionDataOneof,
+                subscribe_update::UpdateOneof,
+                subscribe_update_batch_item::UpdateOneof as BatchItemUpdateOneof, SubscribeRequest,
+                SubscribeRequestAccountsCoalesce, SubscribeRequestAccountsDataSlice,
+                SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
+                SubscribeRequestFilterAccountsFilterAnyOf,
+                SubscribeRequestFilterAccountsFilterDatasizeRange,
+                SubscribeRequestFilterAccountsFilterLamports,
+                SubscribeRequestFilterAccountsFilterMemcmp,
+                SubscribeRequestFilterAccountsFilterNot, SubscribeRequestFilterTransactions,
+                SubscribeRequestFilterTransactionsInstruction,
+                SubscribeRequestFilterTransactionsTokenBalance, SubscribeRequestOverflow,
+                SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateBatchFilters,
+                SubscribeUpdateCommitmentUpgrade, SubscribeUpdateCommitmentUpgradeAccount,
+            },
+            prelude::{CommitmentLevel, OverflowPolicy, TokenBalance, UiTokenAmount},
+            prost::Message as _,
         },
     };
 
+    fn decode_update(update: &FilteredUpdate) -> SubscribeUpdate {
+        let mut buf = Vec::with_capacity(update.encoded_len());
+        update.encode(&mut buf);
+        assert_eq!(buf.len(), update.encoded_len());
+        SubscribeUpdate::decode(buf.as_slice()).expect("valid SubscribeUpdate")
+    }
+
+    fn create_message_account(owner: Pubkey, lamports: u64, data: Vec<u8>) -> MessageAccount {
+        MessageAccount {
+            account: MessageAccountInfo {
+                pubkey: Pubkey::new_unique(),
+                lamports,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+                data,
+                write_version: 1,
+                txn_signature: None,
+            },
+            slot: 100,
+            is_startup: false,
+        }
+    }
+
     fn create_message_transaction(
         keypair: &Keypair,
         account_keys: Vec<Pubkey>,
@@ -922,18 +2295,7 @@ mod tests {
     #[test]
     fn test_filters_all_empty() {
         // ensure Filter can be created with empty values
-        let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
-            transactions: HashMap::new(),
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
-        };
+        let config = SubscribeRequest::default();
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit);
         assert!(filter.is_ok());
@@ -945,24 +2307,12 @@ mod tests {
 
         accounts.insert(
             "solend".to_owned(),
-            SubscribeRequestFilterAccounts {
-                account: vec![],
-                owner: vec![],
-                filters: vec![],
-            },
+            SubscribeRequestFilterAccounts::default(),
         );
 
         let config = SubscribeRequest {
             accounts,
-            slots: HashMap::new(),
-            transactions: HashMap::new(),
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let mut limit = ConfigGrpcFilters::default();
         limit.accounts.any = false;
@@ -972,68 +2322,866 @@ mod tests {
     }
 
     #[test]
-    fn test_filters_transaction_empty() {
-        let mut transactions = HashMap::new();
+    fn test_filters_account_lamports() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
 
-        transactions.insert(
-            "serum".to_string(),
-            SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
-                account_include: vec![],
-                account_exclude: vec![],
-                account_required: vec![],
+        accounts.insert(
+            "funded".to_owned(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![owner.to_string()],
+                filters: vec![SubscribeRequestFilterAccountsFilter {
+                    filter: Some(AccountsFilterDataOneof::Lamports(
+                        SubscribeRequestFilterAccountsFilterLamports {
+                            cmp: Some(AccountsFilterLamports::Gt(1_000)),
+                        },
+                    )),
+                }],
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
-            transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            accounts,
+            ..Default::default()
         };
-        let mut limit = ConfigGrpcFilters::default();
-        limit.transactions.any = false;
-        let filter = Filter::new(&config, &limit);
-        // filter should fail
-        assert!(filter.is_err());
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let message = Message::Account(create_message_account(owner, 1_001, vec![]));
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, vec!["funded"]);
+
+        let message = Message::Account(create_message_account(owner, 1_000, vec![]));
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
     }
 
     #[test]
-    fn test_filters_transaction_not_null() {
-        let mut transactions = HashMap::new();
-        transactions.insert(
-            "serum".to_string(),
-            SubscribeRequestFilterTransactions {
-                vote: Some(true),
-                failed: None,
-                signature: None,
-                account_include: vec![],
-                account_exclude: vec![],
-                account_required: vec![],
+    fn test_filters_account_token_mint_owner() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        accounts.insert(
+            "mint_owner".to_owned(),
+            SubscribeRequestFilterAccounts {
+                filters: vec![
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::TokenMint(mint.to_string())),
+                    },
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::TokenOwner(owner.to_string())),
+                    },
+                ],
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
-            transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            accounts,
+            ..Default::default()
         };
-        let mut limit = ConfigGrpcFilters::default();
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let create_account = |mint: Pubkey, owner: Pubkey| TokenAccount {
+            mint,
+            owner,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+
+        // SPL Token account
+        let spl_token_id = Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap();
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(create_account(mint, owner), &mut data).unwrap();
+        let message = Message::Account(create_message_account(spl_token_id, 0, data.clone()));
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, vec!["mint_owner"]);
+
+        // Token-2022 account with extension
+        let len = ExtensionType::try_calculate_account_len::<TokenAccount>(&[
+            ExtensionType::ImmutableOwner,
+        ])
+        .unwrap();
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut data).unwrap();
+        state.base = create_account(mint, owner);
+        state.pack_base();
+        state.init_account_type().unwrap();
+        state.init_extension::<ImmutableOwner>(true).unwrap();
+        let message = Message::Account(create_message_account(spl_token_2022::ID, 0, data));
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, vec!["mint_owner"]);
+
+        // same data owned by non-token program
+        let message = Message::Account(create_message_account(Pubkey::new_unique(), 0, data));
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
+
+        // other owner
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(create_account(mint, Pubkey::new_unique()), &mut data).unwrap();
+        let message = Message::Account(create_message_account(spl_token_2022::ID, 0, data));
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_filters_account_commitment() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        for (name, commitment) in [
+            ("processed", None),
+            ("finalized", Some(CommitmentLevel::Finalized as i32)),
+        ] {
+            accounts.insert(
+                name.to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    commitment,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let config = SubscribeRequest {
+            accounts,
+            commitment: Some(CommitmentLevel::Processed as i32),
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert!(filter.contains_commitment_level(CommitmentLevel::Processed));
+        assert!(!filter.contains_commitment_level(CommitmentLevel::Confirmed));
+        assert!(filter.contains_commitment_level(CommitmentLevel::Finalized));
+
+        let message = Message::Account(create_message_account(owner, 0, vec![]));
+        for (commitment, expected) in [
+            (CommitmentLevel::Processed, vec!["processed"]),
+            (CommitmentLevel::Confirmed, vec![]),
+            (CommitmentLevel::Finalized, vec!["finalized"]),
+        ] {
+            let updates = filter
+                .get_filters(&message, Some(commitment))
+                .collect::<Vec<_>>();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].0, expected);
+        }
+    }
+
+    #[test]
+    fn test_filters_commitments() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        for (name, commitment) in [
+            ("default", None),
+            ("confirmed", Some(CommitmentLevel::Confirmed as i32)),
+        ] {
+            accounts.insert(
+                name.to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    commitment,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut config = SubscribeRequest {
+            accounts,
+            commitment: Some(CommitmentLevel::Processed as i32),
+            commitments: vec![
+                CommitmentLevel::Finalized as i32,
+                CommitmentLevel::Processed as i32,
+            ],
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        assert!(Filter::new(&config, &limit).is_err());
+
+        config.commitment = None;
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(
+            filter.get_commitment_levels(),
+            &[
+                CommitmentLevel::Processed,
+                CommitmentLevel::Confirmed,
+                CommitmentLevel::Finalized
+            ]
+        );
+
+        let message = Message::Account(create_message_account(owner, 0, vec![]));
+        for (commitment, expected) in [
+            (CommitmentLevel::Processed, vec!["default"]),
+            (CommitmentLevel::Confirmed, vec!["confirmed"]),
+            (CommitmentLevel::Finalized, vec!["default"]),
+        ] {
+            let cache = MessagesEncodeCache::default();
+            let updates = filter
+                .get_update(&message, Some(commitment), &cache)
+                .collect::<Vec<_>>();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].filters, expected);
+            assert_eq!(updates[0].commitment, Some(commitment as i32));
+        }
+    }
+
+    #[test]
+    fn test_filters_encode_cache() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        for (name, data_slice) in [
+            ("a", vec![]),
+            ("b", vec![]),
+            (
+                "slice",
+                vec![SubscribeRequestAccountsDataSlice {
+                    offset: 1,
+                    length: 2,
+                }],
+            ),
+        ] {
+            accounts.insert(
+                name.to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    data_slice,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let cache = MessagesEncodeCache::default();
+        let message = Message::Account(create_message_account(owner, 0, vec![1, 2, 3, 4]));
+        let get_encoded = |filter: &Filter| {
+            let mut updates = filter
+                .get_update(&message, None, &cache)
+                .map(|update| match update.update_oneof {
+                    FilteredUpdateOneof::Encoded(message) => (update.filters, message.data),
+                    _ => panic!("expected encoded update"),
+                })
+                .collect::<Vec<_>>();
+            updates.sort_by(|a, b| a.0.cmp(&b.0));
+            updates
+        };
+
+        // same message and data slice is encoded once and shared across clients
+        let updates = get_encoded(&filter);
+        let updates_other = get_encoded(&filter.clone());
+        assert_eq!(updates.len(), 2);
+        for ((filters, data), (filters_other, data_other)) in updates.iter().zip(updates_other) {
+            assert_eq!(*filters, filters_other);
+            assert_eq!(data.as_ptr(), data_other.as_ptr());
+        }
+        assert_ne!(updates[0].1, updates[1].1);
+    }
+
+    #[test]
+    fn test_filters_account_data_slice() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        for (name, data_slice) in [
+            ("full", vec![]),
+            (
+                "slice",
+                vec![SubscribeRequestAccountsDataSlice {
+                    offset: 1,
+                    length: 2,
+                }],
+            ),
+        ] {
+            accounts.insert(
+                name.to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    data_slice,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let message = Message::Account(create_message_account(owner, 0, vec![1, 2, 3, 4]));
+        let cache = MessagesEncodeCache::default();
+        let mut updates = filter
+            .get_update(&message, None, &cache)
+            .map(|update| match decode_update(&update).update_oneof {
+                Some(UpdateOneof::Account(SubscribeUpdateAccount {
+                    account: Some(account),
+                    ..
+                })) => (update.filters, account.data),
+                _ => panic!("expected account update"),
+            })
+            .collect::<Vec<_>>();
+        updates.sort();
+        assert_eq!(
+            updates,
+            vec![
+                (vec!["full".to_owned()], vec![1, 2, 3, 4]),
+                (vec!["slice".to_owned()], vec![2, 3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filters_account_any_of_not() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        let memcmp = |offset: u64, data: Vec<u8>| SubscribeRequestFilterAccountsFilter {
+            filter: Some(AccountsFilterDataOneof::Memcmp(
+                SubscribeRequestFilterAccountsFilterMemcmp {
+                    offset,
+                    data: Some(AccountsFilterMemcmpOneof::Bytes(data)),
+                    ..Default::default()
+                },
+            )),
+        };
+
+        // datasize 4 AND (memcmp A OR memcmp B) AND NOT memcmp C
+        accounts.insert(
+            "pools".to_owned(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![owner.to_string()],
+                filters: vec![
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::Datasize(4)),
+                    },
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::AnyOf(
+                            SubscribeRequestFilterAccountsFilterAnyOf {
+                                filters: vec![memcmp(0, vec![1]), memcmp(0, vec![2])],
+                            },
+                        )),
+                    },
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::Not(
+                            SubscribeRequestFilterAccountsFilterNot {
+                                filters: vec![memcmp(3, vec![9])],
+                            },
+                        )),
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+
+        let mut limit = ConfigGrpcFilters::default();
+        assert!(Filter::new(&config, &limit).is_err());
+        limit.accounts.filters_max = 5;
+        assert!(Filter::new(&config, &limit).is_err());
+
+        limit.accounts.filters_max = 6;
+        limit.accounts.nested_max = 0;
+        assert!(Filter::new(&config, &limit).is_err());
+
+        limit.accounts.nested_max = 1;
+        let filter = Filter::new(&config, &limit).unwrap();
+        for (data, expected) in [
+            (vec![1, 0, 0, 0], vec!["pools"]),
+            (vec![2, 0, 0, 0], vec!["pools"]),
+            (vec![3, 0, 0, 0], vec![]),
+            (vec![1, 0, 0, 9], vec![]),
+            (vec![1, 0, 0, 0, 0], vec![]),
+        ] {
+            let message = Message::Account(create_message_account(owner, 0, data));
+            let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].0, expected);
+        }
+    }
+
+    #[test]
+    fn test_filters_account_memcmp_mask_datasize_range() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        // datasize in 4..=6 AND byte 1 has high bit set
+        accounts.insert(
+            "masked".to_owned(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![owner.to_string()],
+                filters: vec![
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::DatasizeRange(
+                            SubscribeRequestFilterAccountsFilterDatasizeRange {
+                                min: Some(4),
+                                max: Some(6),
+                            },
+                        )),
+                    },
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilterDataOneof::Memcmp(
+                            SubscribeRequestFilterAccountsFilterMemcmp {
+                                offset: 1,
+                                data: Some(AccountsFilterMemcmpOneof::Bytes(vec![0xff])),
+                                mask: Some(vec![0x80]),
+                            },
+                        )),
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+        for (data, expected) in [
+            (vec![0, 0x80, 0, 0], vec!["masked"]),
+            (vec![0, 0x81, 0, 0, 0, 0], vec!["masked"]),
+            (vec![0, 0x7f, 0, 0], vec![]),
+            (vec![0, 0x80, 0], vec![]),
+            (vec![0, 0x80, 0, 0, 0, 0, 0], vec![]),
+        ] {
+            let message = Message::Account(create_message_account(owner, 0, data));
+            let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].0, expected);
+        }
+
+        // mask length should be equal to data length
+        let mut config = config;
+        config.accounts.get_mut("masked").unwrap().filters =
+            vec![SubscribeRequestFilterAccountsFilter {
+                filter: Some(AccountsFilterDataOneof::Memcmp(
+                    SubscribeRequestFilterAccountsFilterMemcmp {
+                        offset: 0,
+                        data: Some(AccountsFilterMemcmpOneof::Bytes(vec![1, 2])),
+                        mask: Some(vec![0xff]),
+                        ..Default::default()
+                    },
+                )),
+            }];
+        assert!(Filter::new(&config, &limit).is_err());
+
+        // min should not be greater than max
+        config.accounts.get_mut("masked").unwrap().filters =
+            vec![SubscribeRequestFilterAccountsFilter {
+                filter: Some(AccountsFilterDataOneof::DatasizeRange(
+                    SubscribeRequestFilterAccountsFilterDatasizeRange {
+                        min: Some(2),
+                        max: Some(1),
+                    },
+                )),
+            }];
+        assert!(Filter::new(&config, &limit).is_err());
+    }
+
+    #[test]
+    fn test_filters_accounts_coalesce() {
+        let mut config = SubscribeRequest::default();
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(filter.get_accounts_coalesce(), None);
+
+        config.accounts_coalesce = Some(SubscribeRequestAccountsCoalesce { interval_ms: None });
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(
+            filter.get_accounts_coalesce(),
+            Some(FilterAccountsCoalesce::Slot)
+        );
+
+        config.accounts_coalesce = Some(SubscribeRequestAccountsCoalesce {
+            interval_ms: Some(250),
+        });
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(
+            filter.get_accounts_coalesce(),
+            Some(FilterAccountsCoalesce::Interval(Duration::from_millis(250)))
+        );
+
+        config.accounts_coalesce = Some(SubscribeRequestAccountsCoalesce {
+            interval_ms: Some(0),
+        });
+        assert!(Filter::new(&config, &limit).is_err());
+    }
+
+    #[test]
+    fn test_filters_overflow() {
+        let mut config = SubscribeRequest::default();
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(filter.get_overflow(), FilterOverflow::default());
+        assert_eq!(filter.get_overflow().policy, OverflowPolicy::Disconnect);
+
+        config.overflow = Some(SubscribeRequestOverflow {
+            policy: OverflowPolicy::Sample as i32,
+            rate_limit: Some(100),
+            sample_rate: None,
+        });
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert_eq!(
+            filter.get_overflow(),
+            FilterOverflow {
+                policy: OverflowPolicy::Sample,
+                rate_limit: Some(100),
+                sample_rate: 10,
+            }
+        );
+
+        // rate limit requires policy that can drop updates
+        config.overflow = Some(SubscribeRequestOverflow {
+            policy: OverflowPolicy::Disconnect as i32,
+            rate_limit: Some(100),
+            sample_rate: None,
+        });
+        assert!(Filter::new(&config, &limit).is_err());
+
+        config.overflow = Some(SubscribeRequestOverflow {
+            policy: OverflowPolicy::DropOldest as i32,
+            rate_limit: None,
+            sample_rate: Some(0),
+        });
+        assert!(Filter::new(&config, &limit).is_err());
+
+        config.overflow = Some(SubscribeRequestOverflow {
+            policy: 42,
+            rate_limit: None,
+            sample_rate: None,
+        });
+        assert!(Filter::new(&config, &limit).is_err());
+    }
+
+    #[test]
+    fn test_filters_commitment_upgrade() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+        accounts.insert(
+            "owner".to_owned(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![owner.to_string()],
+                ..Default::default()
+            },
+        );
+
+        let mut config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+
+        let message_matched = create_message_account(owner, 0, vec![]);
+        let pubkey = message_matched.account.pubkey;
+        let mut message_matched_next = create_message_account(owner, 0, vec![]);
+        message_matched_next.slot = 101;
+        let pubkey_next = message_matched_next.account.pubkey;
+        let messages = vec![
+            Arc::new(Message::Account(message_matched)),
+            Arc::new(Message::Account(create_message_account(
+                Pubkey::new_unique(),
+                0,
+                vec![],
+            ))),
+            Arc::new(Message::Account(message_matched_next)),
+        ];
+
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert!(filter
+            .get_commitment_upgrades(&messages, CommitmentLevel::Confirmed)
+            .is_empty());
+
+        config.commitment_upgrades = Some(true);
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert!(filter
+            .get_commitment_upgrades(&messages, CommitmentLevel::Processed)
+            .is_empty());
+        assert!(filter
+            .get_commitment_upgrades(&messages[1..2], CommitmentLevel::Confirmed)
+            .is_empty());
+
+        // one update per slot
+        let updates = filter
+            .get_commitment_upgrades(&messages, CommitmentLevel::Finalized)
+            .iter()
+            .map(|update| decode_update(update).update_oneof)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            updates,
+            [(100, pubkey), (101, pubkey_next)]
+                .into_iter()
+                .map(|(slot, pubkey)| Some(UpdateOneof::CommitmentUpgrade(
+                    SubscribeUpdateCommitmentUpgrade {
+                        slot,
+                        status: CommitmentLevel::Finalized as i32,
+                        signatures: vec![],
+                        accounts: vec![SubscribeUpdateCommitmentUpgradeAccount {
+                            pubkey: pubkey.as_ref().into(),
+                            write_version: 1,
+                        }],
+                    }
+                )))
+                .collect::<Vec<_>>()
+        );
+
+        // candidates selected by index give the same updates
+        let index = FiltersIndex::default();
+        let version = index.update(0, Some(&filter));
+        let matches = index.get_matches(&messages);
+        let candidates = messages
+            .iter()
+            .enumerate()
+            .filter(|(index, _message)| matches.is_match(*index, 0, version))
+            .map(|(_index, message)| message)
+            .collect::<Vec<_>>();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            filter
+                .get_commitment_upgrades(candidates, CommitmentLevel::Finalized)
+                .iter()
+                .map(|update| decode_update(update).update_oneof)
+                .collect::<Vec<_>>(),
+            updates
+        );
+
+        // accounts sent at confirmed are not included
+        config.accounts.get_mut("owner").unwrap().commitment =
+            Some(CommitmentLevel::Confirmed as i32);
+        let filter = Filter::new(&config, &limit).unwrap();
+        assert!(filter
+            .get_commitment_upgrades(&messages, CommitmentLevel::Finalized)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_filters_batch() {
+        let owner = Pubkey::new_unique();
+        let mut accounts = HashMap::new();
+
+        for (name, owner) in [("b", owner), ("a", owner), ("c", Pubkey::new_unique())] {
+            accounts.insert(
+                name.to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    ..Default::default()
+                },
+            );
+        }
+
+        let config = SubscribeRequest {
+            accounts,
+            batch: Some(true),
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let message = filter.get_batch_filters_msg().unwrap();
+        assert_eq!(
+            decode_update(&message).update_oneof,
+            Some(UpdateOneof::BatchFilters(SubscribeUpdateBatchFilters {
+                filters: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]
+            }))
+        );
+
+        let cache = MessagesEncodeCache::default();
+        let messages = [
+            Message::Account(create_message_account(owner, 0, vec![])),
+            Message::Account(create_message_account(owner, 0, vec![])),
+        ];
+        let updates = messages
+            .iter()
+            .flat_map(|message| {
+                filter.get_update(message, Some(CommitmentLevel::Processed), &cache)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(updates.len(), 2);
+
+        let batch = filter.get_batch(updates, CommitmentLevel::Processed);
+        assert_eq!(batch.len(), 1);
+        let FilteredUpdateOneof::Batch { filters, .. } = &batch[0].update_oneof else {
+            panic!("expected batch update");
+        };
+        let mut filters = filters.clone();
+        filters.sort();
+        assert_eq!(filters, vec![("a".to_owned(), 2), ("b".to_owned(), 2)]);
+        let batch = decode_update(&batch[0]);
+        assert!(batch.filters.is_empty());
+        assert_eq!(batch.commitment, Some(CommitmentLevel::Processed as i32));
+        let Some(UpdateOneof::Batch(batch)) = batch.update_oneof else {
+            panic!("expected batch update");
+        };
+        assert_eq!(batch.updates.len(), 2);
+        for item in batch.updates.iter() {
+            let mut ids = item.filters.clone();
+            ids.sort();
+            assert_eq!(ids, vec![0, 1]);
+            assert!(matches!(
+                item.update_oneof,
+                Some(BatchItemUpdateOneof::Account(_))
+            ));
+        }
+
+        assert!(filter
+            .get_batch(vec![], CommitmentLevel::Processed)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_filters_index() {
+        let owner_a = Pubkey::new_unique();
+        let owner_b = Pubkey::new_unique();
+        let limit = ConfigGrpcFilters::default();
+        let create_filter = |owner: Pubkey| {
+            let mut accounts = HashMap::new();
+            accounts.insert(
+                "owner".to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    ..Default::default()
+                },
+            );
+            let mut transactions = HashMap::new();
+            transactions.insert(
+                "owner".to_owned(),
+                SubscribeRequestFilterTransactions {
+                    account_include: vec![owner.to_string()],
+                    ..Default::default()
+                },
+            );
+            let config = SubscribeRequest {
+                accounts,
+                transactions,
+                ..Default::default()
+            };
+            Filter::new(&config, &limit).unwrap()
+        };
+
+        let index = FiltersIndex::default();
+        let version_a = index.update(1, Some(&create_filter(owner_a)));
+        let version_b = index.update(2, Some(&create_filter(owner_b)));
+
+        let keypair = Keypair::new();
+        let messages = [
+            Message::Account(create_message_account(owner_a, 0, vec![])),
+            Message::Account(create_message_account(owner_b, 0, vec![])),
+            Message::Transaction(create_message_transaction(
+                &keypair,
+                vec![keypair.pubkey(), owner_b],
+            )),
+        ]
+        .into_iter()
+        .map(Arc::new)
+        .collect::<Vec<_>>();
+
+        let matches = index.get_matches(&messages);
+        assert!(matches.is_match(0, 1, version_a));
+        assert!(!matches.is_match(0, 2, version_b));
+        assert!(!matches.is_match(1, 1, version_a));
+        assert!(matches.is_match(1, 2, version_b));
+        assert!(!matches.is_match(2, 1, version_a));
+        assert!(matches.is_match(2, 2, version_b));
+
+        // filter updated after matches were created should not miss messages
+        let version_a = index.update(1, Some(&create_filter(owner_b)));
+        assert!(matches.is_match(1, 1, version_a));
+
+        index.update(2, None);
+        let matches = index.get_matches(&messages);
+        assert!(matches.is_match(1, 1, version_a));
+        assert!(!matches.is_match(1, 2, version_a));
+
+        // clients without required pubkeys are not stored per message
+        let mut accounts = HashMap::new();
+        accounts.insert("any".to_owned(), SubscribeRequestFilterAccounts::default());
+        let config = SubscribeRequest {
+            accounts,
+            ..Default::default()
+        };
+        let version_any = index.update(3, Some(&Filter::new(&config, &limit).unwrap()));
+        let matches = index.get_matches(&messages);
+        assert_eq!(matches.accounts_any, vec![3]);
+        assert!(matches.transactions_any.is_empty());
+        assert!(matches
+            .clients
+            .iter()
+            .flatten()
+            .all(|(_kind, ids)| !ids.contains(&3)));
+        assert!(matches.is_match(0, 3, version_any));
+        assert!(matches.is_match(1, 3, version_any));
+        assert!(!matches.is_match(2, 3, version_any));
+        assert!(matches.is_match(1, 1, version_any));
+        assert!(!matches.is_match(0, 1, version_any));
+        assert!(index.is_account_interesting(&Pubkey::new_unique(), &Pubkey::new_unique()));
+        index.update(3, None);
+        assert!(!index.is_account_interesting(&Pubkey::new_unique(), &Pubkey::new_unique()));
+
+        assert!(index.is_account_interesting(&Pubkey::new_unique(), &owner_b));
+        assert!(!index.is_account_interesting(&Pubkey::new_unique(), &owner_a));
+        index.update(1, None);
+        assert!(!index.is_account_interesting(&Pubkey::new_unique(), &owner_b));
+    }
+
+    #[test]
+    fn test_filters_transaction_empty() {
+        let mut transactions = HashMap::new();
+
+        transactions.insert(
+            "serum".to_string(),
+            SubscribeRequestFilterTransactions::default(),
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let mut limit = ConfigGrpcFilters::default();
+        limit.transactions.any = false;
+        let filter = Filter::new(&config, &limit);
+        // filter should fail
+        assert!(filter.is_err());
+    }
+
+    #[test]
+    fn test_filters_transaction_not_null() {
+        let mut transactions = HashMap::new();
+        transactions.insert(
+            "serum".to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(true),
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let mut limit = ConfigGrpcFilters::default();
         limit.transactions.any = false;
         let filter_res = Filter::new(&config, &limit);
         // filter should succeed
@@ -1052,26 +3200,14 @@ mod tests {
         transactions.insert(
             "serum".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
                 account_include,
-                account_exclude: vec![],
-                account_required: vec![],
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
             transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit).unwrap();
@@ -1099,26 +3235,14 @@ mod tests {
         transactions.insert(
             "serum".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
                 account_include,
-                account_exclude: vec![],
-                account_required: vec![],
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
             transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit).unwrap();
@@ -1146,26 +3270,14 @@ mod tests {
         transactions.insert(
             "serum".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
-                account_include: vec![],
                 account_exclude,
-                account_required: vec![],
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
             transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit).unwrap();
@@ -1196,26 +3308,15 @@ mod tests {
         transactions.insert(
             "serum".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
                 account_include,
-                account_exclude: vec![],
                 account_required,
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
             transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit).unwrap();
@@ -1251,26 +3352,15 @@ mod tests {
         transactions.insert(
             "serum".to_string(),
             SubscribeRequestFilterTransactions {
-                vote: None,
-                failed: None,
-                signature: None,
                 account_include,
-                account_exclude: vec![],
                 account_required,
+                ..Default::default()
             },
         );
 
         let config = SubscribeRequest {
-            accounts: HashMap::new(),
-            slots: HashMap::new(),
             transactions,
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: None,
-            accounts_data_slice: Vec::new(),
-            ping: None,
+            ..Default::default()
         };
         let limit = ConfigGrpcFilters::default();
         let filter = Filter::new(&config, &limit).unwrap();
@@ -1282,4 +3372,289 @@ mod tests {
             assert!(filters.is_empty());
         }
     }
+
+    #[test]
+    fn test_transaction_program_invoked_inner() {
+        let mut transactions = HashMap::new();
+
+        let keypair_x = Keypair::new();
+        let account_key_x = keypair_x.pubkey();
+        let account_key_y = Pubkey::new_unique();
+        let program_z = Pubkey::new_unique();
+
+        transactions.insert(
+            "serum".to_string(),
+            SubscribeRequestFilterTransactions {
+                program_invoked: vec![program_z.to_string()],
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        // program is used only as an account
+        let mut message_transaction =
+            create_message_transaction(&keypair_x, vec![account_key_x, account_key_y, program_z]);
+        let message = Message::Transaction(message_transaction.clone());
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
+
+        // program is invoked with CPI
+        message_transaction.transaction.meta.inner_instructions = Some(vec![InnerInstructions {
+            index: 0,
+            instructions: vec![InnerInstruction {
+                instruction: CompiledInstruction::new_from_raw_parts(2, vec![], vec![1]),
+                stack_height: Some(2),
+            }],
+        }]);
+        let message = Message::Transaction(message_transaction);
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].0, vec!["serum"]);
+        assert!(matches!(updates[0].1, MessageRef::Transaction(_)));
+    }
+
+    #[test]
+    fn test_transaction_instructions_data() {
+        let mut transactions = HashMap::new();
+
+        let keypair_x = Keypair::new();
+        let account_key_x = keypair_x.pubkey();
+        let account_key_y = Pubkey::new_unique();
+        let program_z = Pubkey::new_unique();
+
+        transactions.insert(
+            "swap".to_string(),
+            SubscribeRequestFilterTransactions {
+                instructions: vec![SubscribeRequestFilterTransactionsInstruction {
+                    program_id: program_z.to_string(),
+                    data: Some(TransactionsFilterInstructionDataOneof::Base58(
+                        bs58::encode([0xf8, 0xc6]).into_string(),
+                    )),
+                }],
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        // program is invoked with other data
+        let mut message_transaction =
+            create_message_transaction(&keypair_x, vec![account_key_x, account_key_y, program_z]);
+        message_transaction.transaction.meta.inner_instructions = Some(vec![InnerInstructions {
+            index: 0,
+            instructions: vec![InnerInstruction {
+                instruction: CompiledInstruction::new_from_raw_parts(2, vec![0xf8, 0x01], vec![1]),
+                stack_height: Some(2),
+            }],
+        }]);
+        let message = Message::Transaction(message_transaction.clone());
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
+
+        // program is invoked with matched data prefix
+        message_transaction.transaction.meta.inner_instructions = Some(vec![InnerInstructions {
+            index: 0,
+            instructions: vec![InnerInstruction {
+                instruction: CompiledInstruction::new_from_raw_parts(
+                    2,
+                    vec![0xf8, 0xc6, 0x01, 0x02],
+                    vec![1],
+                ),
+                stack_height: Some(2),
+            }],
+        }]);
+        let message = Message::Transaction(message_transaction);
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].0, vec!["swap"]);
+        assert!(matches!(updates[0].1, MessageRef::Transaction(_)));
+    }
+
+    #[test]
+    fn test_transaction_include_signer_writable() {
+        let mut transactions = HashMap::new();
+
+        let keypair_x = Keypair::new();
+        let account_key_x = keypair_x.pubkey();
+        let account_key_y = Pubkey::new_unique();
+
+        let create_filter = |account_include_signer: Vec<String>,
+                             account_required_writable: Vec<String>| {
+            SubscribeRequestFilterTransactions {
+                account_include_signer,
+                account_required_writable,
+                ..Default::default()
+            }
+        };
+        transactions.insert(
+            "signer_x".to_string(),
+            create_filter(vec![account_key_x.to_string()], vec![]),
+        );
+        transactions.insert(
+            "signer_y".to_string(),
+            create_filter(vec![account_key_y.to_string()], vec![]),
+        );
+        transactions.insert(
+            "writable_xy".to_string(),
+            create_filter(
+                vec![],
+                vec![account_key_x.to_string(), account_key_y.to_string()],
+            ),
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        // x is the only signer, both accounts are writable
+        let message_transaction =
+            create_message_transaction(&keypair_x, vec![account_key_x, account_key_y]);
+        let message = Message::Transaction(message_transaction);
+        let mut updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates.len(), 2);
+        updates[0].0.sort();
+        assert_eq!(updates[0].0, vec!["signer_x", "writable_xy"]);
+        assert!(matches!(updates[0].1, MessageRef::Transaction(_)));
+    }
+
+    fn create_token_balance(
+        account_index: u32,
+        mint: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+    ) -> TransactionTokenBalance {
+        convert_from::create_token_balances(vec![TokenBalance {
+            account_index,
+            mint: mint.to_string(),
+            ui_token_amount: Some(UiTokenAmount {
+                ui_amount: amount as f64,
+                decimals: 0,
+                amount: amount.to_string(),
+                ui_amount_string: amount.to_string(),
+            }),
+            owner: owner.to_string(),
+            program_id: String::new(),
+        }])
+        .unwrap()
+        .remove(0)
+    }
+
+    #[test]
+    fn test_transaction_token_balance() {
+        let mut transactions = HashMap::new();
+
+        let keypair_x = Keypair::new();
+        let account_key_x = keypair_x.pubkey();
+        let mint = Pubkey::new_unique();
+        let owner_a = Pubkey::new_unique();
+        let owner_b = Pubkey::new_unique();
+
+        let create_filter = |owner: Vec<String>| SubscribeRequestFilterTransactions {
+            token_balance: vec![SubscribeRequestFilterTransactionsTokenBalance {
+                mint: mint.to_string(),
+                owner,
+            }],
+            ..Default::default()
+        };
+        transactions.insert("mint".to_string(), create_filter(vec![]));
+        transactions.insert(
+            "mint_owner_a".to_string(),
+            create_filter(vec![owner_a.to_string()]),
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let limit = ConfigGrpcFilters::default();
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let mut message_transaction = create_message_transaction(&keypair_x, vec![account_key_x]);
+        let mut get_filters = |pre: Vec<TransactionTokenBalance>,
+                               post: Vec<TransactionTokenBalance>| {
+            message_transaction.transaction.meta.pre_token_balances = Some(pre);
+            message_transaction.transaction.meta.post_token_balances = Some(post);
+            let message = Message::Transaction(message_transaction.clone());
+            let mut updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+            assert_eq!(updates.len(), 2);
+            updates[0].0.sort();
+            updates.remove(0).0
+        };
+
+        // balances are not changed
+        let filters = get_filters(
+            vec![create_token_balance(1, mint, owner_a, 10)],
+            vec![create_token_balance(1, mint, owner_a, 10)],
+        );
+        assert!(filters.is_empty());
+
+        // balance of other owner is changed
+        let filters = get_filters(
+            vec![create_token_balance(1, mint, owner_b, 10)],
+            vec![create_token_balance(1, mint, owner_b, 5)],
+        );
+        assert_eq!(filters, vec!["mint"]);
+
+        // token account of the owner is created
+        let filters = get_filters(vec![], vec![create_token_balance(1, mint, owner_a, 5)]);
+        assert_eq!(filters, vec!["mint", "mint_owner_a"]);
+    }
+
+    #[test]
+    fn test_transaction_log_contains() {
+        let mut transactions = HashMap::new();
+
+        let keypair_x = Keypair::new();
+        let account_key_x = keypair_x.pubkey();
+
+        transactions.insert(
+            "events".to_string(),
+            SubscribeRequestFilterTransactions {
+                log_contains: vec!["Program data: 5Q".to_owned()],
+                ..Default::default()
+            },
+        );
+
+        let config = SubscribeRequest {
+            transactions,
+            ..Default::default()
+        };
+        let mut limit = ConfigGrpcFilters::default();
+        limit.transactions.log_contains_length_max = 8;
+        assert!(Filter::new(&config, &limit).is_err());
+        limit.transactions.log_contains_length_max = 16;
+        let filter = Filter::new(&config, &limit).unwrap();
+
+        let mut message_transaction = create_message_transaction(&keypair_x, vec![account_key_x]);
+        message_transaction.transaction.meta.log_messages =
+            Some(vec!["Program log: Instruction: Swap".to_owned()]);
+        let message = Message::Transaction(message_transaction.clone());
+        for (filters, _message) in filter.get_filters(&message, None) {
+            assert!(filters.is_empty());
+        }
+
+        message_transaction.transaction.meta.log_messages = Some(vec![
+            "Program log: Instruction: Swap".to_owned(),
+            "Program data: 5Qy0dvgOIqU=".to_owned(),
+        ]);
+        let message = Message::Transaction(message_transaction);
+        let updates = filter.get_filters(&message, None).collect::<Vec<_>>();
+        assert_eq!(updates[0].0, vec!["events"]);
+    }
 }
@@ -1,7 +1,17 @@
 use {
     crate::{
-        config::{ConfigBlockFailAction, ConfigGrpc, ConfigGrpcFilters},
-        filters::{Filter, FilterAccountsDataSlice},
+        codec::{
+            geyser::geyser_server::{Geyser, GeyserServer},
+            FilteredUpdate, FilteredUpdateOneof, MessagesEncodeCache, MessagesEncodeCaches,
+        },
+        config::{
+            ConfigBlockFailAction, ConfigGrpc, ConfigGrpcFilters, ConfigGrpcGeyserLoop,
+            ConfigGrpcOverflow, ConfigGrpcUnaryMethod, ConfigGrpcXToken,
+        },
+        filters::{
+            Filter, FilterAccountsCoalesce, FilterAccountsDataSlice, FilterOverflow, FiltersIndex,
+            FiltersIndexMatches,
+        },
         metrics::{self, DebugClientMessage, CONNECTIONS_TOTAL, MESSAGE_QUEUE_SIZE},
         version::GrpcVersionInfo,
     },
@@ -19,7 +29,8 @@ use {
     },
     solana_transaction_status::{Reward, TransactionStatusMeta},
     std::{
-        collections::{BTreeMap, HashMap},
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
+        pin::Pin,
         sync::{
             atomic::{AtomicUsize, Ordering},
             Arc,
@@ -28,9 +39,9 @@ use {
     tokio::{
         fs,
         runtime::Builder,
-        sync::{broadcast, mpsc, Mutex, Notify, RwLock, Semaphore},
+        sync::{broadcast, mpsc, oneshot, Mutex, Notify, RwLock, Semaphore},
         task::spawn_blocking,
-        time::{sleep, Duration, Instant},
+        time::{sleep, Duration, Instant, Sleep},
     },
     tokio_stream::wrappers::ReceiverStream,
     tonic::{
@@ -45,15 +56,14 @@ use {
     yellowstone_grpc_proto::{
         convert_to,
         prelude::{
-            geyser_server::{Geyser, GeyserServer},
-            subscribe_update::UpdateOneof,
-            CommitmentLevel, GetBlockHeightRequest, GetBlockHeightResponse,
-            GetLatestBlockhashRequest, GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse,
-            GetVersionRequest, GetVersionResponse, IsBlockhashValidRequest,
-            IsBlockhashValidResponse, PingRequest, PongResponse, SubscribeRequest, SubscribeUpdate,
-            SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdateBlock,
-            SubscribeUpdateBlockMeta, SubscribeUpdateEntry, SubscribeUpdatePing,
-            SubscribeUpdateSlot, SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo,
+            subscribe_update::UpdateOneof, CommitmentLevel, GetBlockHeightRequest,
+            GetBlockHeightResponse, GetLatestBlockhashRequest, GetLatestBlockhashResponse,
+            GetSlotRequest, GetSlotResponse, GetVersionRequest, GetVersionResponse,
+            IsBlockhashValidRequest, IsBlockhashValidResponse, OverflowPolicy, PingRequest,
+            PongResponse, SubscribeRequest, SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+            SubscribeUpdateBlock, SubscribeUpdateBlockMeta, SubscribeUpdateDropped,
+            SubscribeUpdateEntry, SubscribeUpdateLagged, SubscribeUpdatePing, SubscribeUpdateSlot,
+            SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo,
             SubscribeUpdateTransactionStatus, TransactionError as SubscribeUpdateTransactionError,
         },
     },
@@ -131,6 +141,7 @@ pub struct MessageSlot {
     pub slot: u64,
     pub parent: Option<u64>,
     pub status: CommitmentLevel,
+    pub dead: bool,
 }
 
 impl From<(u64, Option<u64>, SlotStatus)> for MessageSlot {
@@ -143,6 +154,7 @@ impl From<(u64, Option<u64>, SlotStatus)> for MessageSlot {
                 SlotStatus::Confirmed => CommitmentLevel::Confirmed,
                 SlotStatus::Rooted => CommitmentLevel::Finalized,
             },
+            dead: false,
         }
     }
 }
@@ -347,6 +359,14 @@ impl Message {
     }
 }
 
+/// Messages from plugin to geyser loop
+#[derive(Debug)]
+pub enum PluginMessage {
+    Message(Arc<Message>),
+    // account update dropped by plugin, counted only in `updated_account_count` of the block
+    AccountSkipped { slot: u64, pubkey: Pubkey },
+}
+
 #[derive(Debug, Clone)]
 pub struct MessageBlockRef<'a> {
     pub parent_slot: u64,
@@ -419,6 +439,7 @@ impl<'a> MessageRef<'a> {
                 slot: message.slot,
                 parent: message.parent,
                 status: message.status as i32,
+                dead: message.dead,
             }),
             Self::Account(message) => UpdateOneof::Account(SubscribeUpdateAccount {
                 account: Some(message.account.to_proto(accounts_data_slice)),
@@ -673,6 +694,7 @@ struct SlotMessages {
     block_meta: Option<MessageBlockMeta>,
     transactions: Vec<MessageTransactionInfo>,
     accounts_dedup: HashMap<Pubkey, (u64, usize)>, // (write_version, message_index)
+    accounts_skipped: HashSet<Pubkey>,
     entries: Vec<MessageEntry>,
     sealed: bool,
     entries_count: usize,
@@ -705,9 +727,16 @@ impl SlotMessages {
                         }
                     }
 
-                    let message = Arc::new(Message::Block(
-                        (block_meta.clone(), transactions, accounts, entries).into(),
-                    ));
+                    let mut block =
+                        MessageBlock::from((block_meta.clone(), transactions, accounts, entries));
+                    // accounts dropped by plugin are counted too
+                    let accounts_skipped = std::mem::take(&mut self.accounts_skipped);
+                    block.updated_account_count += accounts_skipped
+                        .iter()
+                        .filter(|pubkey| !self.accounts_dedup.contains_key(*pubkey))
+                        .count() as u64;
+
+                    let message = Arc::new(Message::Block(block));
                     self.messages.push(Some(Arc::clone(&message)));
 
                     self.sealed = true;
@@ -721,16 +750,641 @@ impl SlotMessages {
     }
 }
 
+// Tracks parents of not finalized slots to find slots on dead forks and ancestors without status
+#[derive(Debug, Default)]
+struct SlotForks {
+    parents: BTreeMap<u64, Option<u64>>,
+    confirmed: Option<u64>,
+    finalized: Option<u64>,
+    ancestors_statuses: HashSet<(u64, CommitmentLevel)>,
+    keep_slots: u64,
+}
+
+impl SlotForks {
+    fn new(keep_slots: u64) -> Self {
+        Self {
+            keep_slots,
+            ..Default::default()
+        }
+    }
+
+    // returns statuses for ancestors from the latest slot with the same status,
+    // `None` if status for the slot was already emitted
+    fn get_ancestors_statuses(&mut self, message: &MessageSlot) -> Option<Vec<MessageSlot>> {
+        let last_slot = match message.status {
+            CommitmentLevel::Processed => return Some(vec![]),
+            CommitmentLevel::Confirmed => self.confirmed,
+            CommitmentLevel::Finalized => self.finalized,
+        };
+        if self
+            .ancestors_statuses
+            .remove(&(message.slot, message.status))
+        {
+            return None;
+        }
+
+        let mut ancestors = vec![];
+        if let Some(last_slot) = last_slot {
+            let mut current = message
+                .parent
+                .or_else(|| self.parents.get(&message.slot).copied().flatten());
+            while let Some(slot) = current.filter(|slot| *slot > last_slot) {
+                let parent = match self.parents.get(&slot) {
+                    Some(parent) => *parent,
+                    None => break,
+                };
+                self.ancestors_statuses.insert((slot, message.status));
+                ancestors.push(MessageSlot {
+                    slot,
+                    parent,
+                    status: message.status,
+                    dead: false,
+                });
+                current = parent;
+            }
+        }
+        ancestors.reverse();
+
+        let last_slot = Some(last_slot.map_or(message.slot, |slot| slot.max(message.slot)));
+        match message.status {
+            CommitmentLevel::Processed => {}
+            CommitmentLevel::Confirmed => self.confirmed = last_slot,
+            CommitmentLevel::Finalized => self.finalized = last_slot,
+        }
+
+        Some(ancestors)
+    }
+
+    fn update(&mut self, message: &MessageSlot) -> Vec<MessageSlot> {
+        let parent = self.parents.entry(message.slot).or_default();
+        if message.parent.is_some() {
+            *parent = message.parent;
+        }
+        if message.status != CommitmentLevel::Finalized {
+            return vec![];
+        }
+
+        // collect finalized chain while parents are known
+        let finalized = message.slot;
+        let mut chain = HashSet::new();
+        let mut lowest = finalized;
+        let mut current = Some(finalized);
+        while let Some(slot) = current {
+            chain.insert(slot);
+            lowest = slot;
+            current = self.parents.get(&slot).copied().flatten();
+        }
+
+        let mut dead = Vec::new();
+        let mut dead_slots = HashSet::new();
+        for (&slot, &parent) in self.parents.range(lowest..) {
+            let is_dead = match slot.cmp(&finalized) {
+                std::cmp::Ordering::Less => !chain.contains(&slot),
+                std::cmp::Ordering::Equal => false,
+                // not a descendant of the finalized slot
+                std::cmp::Ordering::Greater => match parent {
+                    Some(parent) => parent < finalized || dead_slots.contains(&parent),
+                    None => false,
+                },
+            };
+            if is_dead {
+                dead_slots.insert(slot);
+                dead.push(MessageSlot {
+                    slot,
+                    parent,
+                    status: CommitmentLevel::Processed,
+                    dead: true,
+                });
+            }
+        }
+
+        self.parents = self.parents.split_off(&(finalized + 1));
+        self.parents
+            .retain(|slot, _parent| !dead_slots.contains(slot));
+        // keep extra slots, same as block reconstruction info
+        let keep_slots = self.keep_slots;
+        self.ancestors_statuses
+            .retain(|(slot, _status)| *slot + keep_slots >= finalized);
+        dead
+    }
+}
+
+type BroadcastedMessage = (
+    CommitmentLevel,
+    Arc<Vec<Arc<Message>>>,
+    Arc<MessagesEncodeCache>,
+    Arc<FiltersIndexMatches>,
+);
+
+#[derive(Debug)]
+struct ClientAccountsCoalesceItem {
+    commitment: CommitmentLevel,
+    pubkey: Pubkey,
+    slot: u64,
+    write_version: u64,
+    updates: Vec<FilteredUpdate>,
+}
+
+#[derive(Debug, Default)]
+struct ClientAccountsCoalesce {
+    items: Vec<Option<ClientAccountsCoalesceItem>>, // Option is used for replaced updates
+    dedup: HashMap<(CommitmentLevel, Pubkey), (u64, usize)>, // (write_version, item_index)
+}
+
+impl ClientAccountsCoalesce {
+    // pending updates are flushed on reaching the limit, slot status for commitment can be missed
+    const ITEMS_MAX: usize = 100_000;
+
+    fn is_full(&self) -> bool {
+        self.items.len() >= Self::ITEMS_MAX
+    }
+
+    fn push(
+        &mut self,
+        commitment: CommitmentLevel,
+        message: &MessageAccount,
+        updates: Vec<FilteredUpdate>,
+    ) {
+        let write_version = message.account.write_version;
+        let item_index = self.items.len();
+        match self.dedup.get_mut(&(commitment, message.account.pubkey)) {
+            Some(entry) if entry.0 >= write_version => return,
+            Some(entry) => {
+                self.items[entry.1] = None;
+                *entry = (write_version, item_index);
+            }
+            None => {
+                self.dedup.insert(
+                    (commitment, message.account.pubkey),
+                    (write_version, item_index),
+                );
+            }
+        }
+        self.items.push(Some(ClientAccountsCoalesceItem {
+            commitment,
+            pubkey: message.account.pubkey,
+            slot: message.slot,
+            write_version,
+            updates,
+        }));
+    }
+
+    fn take(&mut self, is_ready: impl Fn(CommitmentLevel, u64) -> bool) -> Vec<FilteredUpdate> {
+        let mut updates = vec![];
+        self.dedup.clear();
+        for item in std::mem::take(&mut self.items).into_iter().flatten() {
+            if is_ready(item.commitment, item.slot) {
+                updates.extend(item.updates);
+            } else {
+                self.dedup.insert(
+                    (item.commitment, item.pubkey),
+                    (item.write_version, self.items.len()),
+                );
+                self.items.push(Some(item));
+            }
+        }
+        updates
+    }
+
+    // updates are packed into batches per commitment level if client requested batches
+    fn take_all(&mut self, filter: &Filter) -> Vec<FilteredUpdate> {
+        let mut commitments: Vec<(CommitmentLevel, Vec<FilteredUpdate>)> = vec![];
+        self.dedup.clear();
+        for item in std::mem::take(&mut self.items).into_iter().flatten() {
+            match commitments
+                .iter_mut()
+                .find(|(commitment, _updates)| *commitment == item.commitment)
+            {
+                Some((_commitment, updates)) => updates.extend(item.updates),
+                None => commitments.push((item.commitment, item.updates)),
+            }
+        }
+        commitments
+            .into_iter()
+            .flat_map(|(commitment, updates)| filter.get_batch(updates, commitment))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClientError {
+    Full,
+    Closed,
+    Lagged(u64), // number of skipped broadcasted batches
+}
+
+impl<T> From<mpsc::error::TrySendError<T>> for ClientError {
+    fn from(error: mpsc::error::TrySendError<T>) -> Self {
+        match error {
+            mpsc::error::TrySendError::Full(_) => Self::Full,
+            mpsc::error::TrySendError::Closed(_) => Self::Closed,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ClientOverflow {
+    buffer: VecDeque<FilteredUpdate>, // used only by drop oldest policy
+    dropped_updates: u64,
+    dropped_filters: HashMap<String, u64>,
+    rate_window: Instant,
+    rate_count: u64,
+    overflowed: bool,
+    sampled: u64,
+    buffer_max: usize,
+    last_slots: HashMap<i32, u64>, // max slot of sent updates per commitment level
+}
+
+impl ClientOverflow {
+    fn new(config: ConfigGrpcOverflow) -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            dropped_updates: 0,
+            dropped_filters: HashMap::new(),
+            rate_window: Instant::now(),
+            rate_count: 0,
+            overflowed: false,
+            sampled: 0,
+            buffer_max: config.buffer_max,
+            last_slots: HashMap::new(),
+        }
+    }
+
+    fn get_last_slot(&self, commitment: CommitmentLevel) -> Option<u64> {
+        self.last_slots.get(&(commitment as i32)).copied()
+    }
+
+    // should be called only for updates accepted by the stream
+    fn set_last_slot(&mut self, message: &FilteredUpdate) {
+        if let (Some(commitment), Some(slot)) = (message.commitment, message.slot) {
+            let last_slot = self.last_slots.entry(commitment).or_default();
+            *last_slot = (*last_slot).max(slot);
+        }
+    }
+
+    fn send_permit(
+        &mut self,
+        permit: mpsc::Permit<'_, TonicResult<FilteredUpdate>>,
+        message: FilteredUpdate,
+    ) {
+        self.set_last_slot(&message);
+        permit.send(Ok(message));
+    }
+
+    fn is_pending(&self) -> bool {
+        !self.buffer.is_empty() || self.dropped_updates > 0
+    }
+
+    fn send(
+        &mut self,
+        stream_tx: &mpsc::Sender<TonicResult<FilteredUpdate>>,
+        overflow: FilterOverflow,
+        message: FilteredUpdate,
+    ) -> Result<(), ClientError> {
+        match overflow.policy {
+            OverflowPolicy::Disconnect => {
+                let permit = stream_tx.try_reserve()?;
+                self.send_permit(permit, message);
+                Ok(())
+            }
+            OverflowPolicy::DropOldest => {
+                self.buffer.push_back(message);
+                if self.buffer.len() > self.buffer_max {
+                    if let Some(message) = self.buffer.pop_front() {
+                        self.drop_update(&message);
+                    }
+                }
+                self.flush(stream_tx, overflow)
+            }
+            OverflowPolicy::DropNewest | OverflowPolicy::Sample => {
+                if overflow.policy == OverflowPolicy::Sample
+                    && self.overflowed
+                    && message.is_account()
+                {
+                    self.sampled += 1;
+                    if self.sampled % overflow.sample_rate != 0 {
+                        self.drop_update(&message);
+                        return Ok(());
+                    }
+                }
+
+                match self.reserve(stream_tx, overflow) {
+                    Ok(permit) => {
+                        self.send_permit(permit, message);
+                        self.overflowed = false;
+                        Ok(())
+                    }
+                    Err(ClientError::Full) => {
+                        self.overflowed = true;
+                        self.drop_update(&message);
+                        Ok(())
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+        }
+    }
+
+    // send buffered updates and dropped notice if stream has capacity
+    fn flush(
+        &mut self,
+        stream_tx: &mpsc::Sender<TonicResult<FilteredUpdate>>,
+        overflow: FilterOverflow,
+    ) -> Result<(), ClientError> {
+        let mut result = Ok(());
+        while !self.buffer.is_empty() {
+            match self.reserve(stream_tx, overflow) {
+                Ok(permit) => {
+                    if let Some(message) = self.buffer.pop_front() {
+                        self.send_permit(permit, message);
+                    }
+                }
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        if result.is_ok() {
+            result = self.send_dropped(stream_tx, overflow);
+        }
+
+        match result {
+            Err(ClientError::Full) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn reserve<'a>(
+        &mut self,
+        stream_tx: &'a mpsc::Sender<TonicResult<FilteredUpdate>>,
+        overflow: FilterOverflow,
+    ) -> Result<mpsc::Permit<'a, TonicResult<FilteredUpdate>>, ClientError> {
+        // dropped notice is always sent before next update
+        self.send_dropped(stream_tx, overflow)?;
+        self.reserve_rate(stream_tx, overflow)
+    }
+
+    // dropped notices are counted by rate limit too
+    fn reserve_rate<'a>(
+        &mut self,
+        stream_tx: &'a mpsc::Sender<TonicResult<FilteredUpdate>>,
+        overflow: FilterOverflow,
+    ) -> Result<mpsc::Permit<'a, TonicResult<FilteredUpdate>>, ClientError> {
+        if let Some(rate_limit) = overflow.rate_limit {
+            let now = Instant::now();
+            if now.duration_since(self.rate_window) >= Duration::from_secs(1) {
+                self.rate_window = now;
+                self.rate_count = 0;
+            }
+            if self.rate_count >= rate_limit {
+                return Err(ClientError::Full);
+            }
+        }
+
+        let permit = stream_tx.try_reserve()?;
+        self.rate_count += 1;
+        Ok(permit)
+    }
+
+    fn send_dropped(
+        &mut self,
+        stream_tx: &mpsc::Sender<TonicResult<FilteredUpdate>>,
+        overflow: FilterOverflow,
+    ) -> Result<(), ClientError> {
+        if self.dropped_updates > 0 {
+            let permit = self.reserve_rate(stream_tx, overflow)?;
+            permit.send(Ok(FilteredUpdate::new(
+                vec![],
+                None,
+                UpdateOneof::Dropped(SubscribeUpdateDropped {
+                    updates: std::mem::take(&mut self.dropped_updates),
+                    filters: std::mem::take(&mut self.dropped_filters),
+                }),
+            )));
+        }
+        Ok(())
+    }
+
+    fn drop_update(&mut self, message: &FilteredUpdate) {
+        match &message.update_oneof {
+            FilteredUpdateOneof::Batch { filters, items } => {
+                self.dropped_updates += items.len() as u64;
+                for (filter, count) in filters.iter() {
+                    *self.dropped_filters.entry(filter.clone()).or_default() += count;
+                }
+            }
+            _ => {
+                self.dropped_updates += 1;
+                for filter in message.filters.iter() {
+                    *self.dropped_filters.entry(filter.clone()).or_default() += 1;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ReplayStoredSlotsRequest {
+    from_slot: u64,
+    tx: oneshot::Sender<Option<ReplayStoredSlotsResponse>>,
+}
+
+#[derive(Debug)]
+struct ReplayStoredSlotsResponse {
+    messages: Vec<BroadcastedMessage>,
+    messages_rx: broadcast::Receiver<BroadcastedMessage>,
+}
+
+#[derive(Debug)]
+struct ReplayStoredSlots {
+    max_slots: u64,
+    last_slot: u64,
+    available_from: Option<u64>,
+    batches: VecDeque<(u64, CommitmentLevel, Arc<Vec<Arc<Message>>>)>, // (max slot in batch, batch)
+}
+
+impl ReplayStoredSlots {
+    const fn new(max_slots: u64) -> Self {
+        Self {
+            max_slots,
+            last_slot: 0,
+            available_from: None,
+            batches: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, commitment: CommitmentLevel, messages: &Arc<Vec<Arc<Message>>>) {
+        if self.max_slots == 0 {
+            return;
+        }
+
+        let slot = match messages.iter().map(|message| message.get_slot()).max() {
+            Some(slot) => slot,
+            None => return,
+        };
+        if self.available_from.is_none() {
+            self.available_from = messages.iter().map(|message| message.get_slot()).min();
+        }
+        self.last_slot = self.last_slot.max(slot);
+        self.batches
+            .push_back((slot, commitment, Arc::clone(messages)));
+
+        // Messages of the removed batch are lost, so replay is possible only from the next slot
+        while let Some((slot, _commitment, _messages)) = self.batches.front() {
+            if slot + self.max_slots >= self.last_slot {
+                break;
+            }
+            let available_from = slot + 1;
+            self.available_from = self.available_from.max(Some(available_from));
+            self.batches.pop_front();
+        }
+    }
+
+    fn get(&self, from_slot: u64) -> Option<Vec<BroadcastedMessage>> {
+        match self.available_from {
+            Some(available_from) if from_slot >= available_from => Some(
+                self.batches
+                    .iter()
+                    .filter(|(slot, _commitment, _messages)| *slot >= from_slot)
+                    // encoded messages are not stored, replayed batches are encoded again
+                    .map(|(_slot, commitment, messages)| {
+                        (
+                            *commitment,
+                            Arc::clone(messages),
+                            Arc::default(),
+                            Arc::default(),
+                        )
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+// Client identified by `x-token` header, with own limits
+#[derive(Debug)]
+struct XToken {
+    name: String,
+    config_filters: Arc<ConfigGrpcFilters>,
+    subscriptions_max: usize,
+    subscriptions: AtomicUsize,
+    unary_allowed: Option<HashSet<ConfigGrpcUnaryMethod>>,
+}
+
+impl XToken {
+    fn new(
+        name: String,
+        config_filters: Arc<ConfigGrpcFilters>,
+        subscriptions_max: usize,
+        unary_allowed: Option<HashSet<ConfigGrpcUnaryMethod>>,
+    ) -> Self {
+        Self {
+            name,
+            config_filters,
+            subscriptions_max,
+            subscriptions: AtomicUsize::new(0),
+            unary_allowed,
+        }
+    }
+
+    fn check_unary(&self, method: ConfigGrpcUnaryMethod) -> TonicResult<()> {
+        match &self.unary_allowed {
+            Some(methods) if !methods.contains(&method) => {
+                Err(Status::permission_denied("method is not allowed"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn subscription_add(&self) -> TonicResult<()> {
+        self.subscriptions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < self.subscriptions_max).then_some(count + 1)
+            })
+            .map(|_count| ())
+            .map_err(|_count| Status::resource_exhausted("max number of subscriptions reached"))
+    }
+
+    fn subscription_remove(&self) {
+        self.subscriptions.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Tokens from the config, `default` is used for `x_token` and connections without auth
+#[derive(Debug, Clone)]
+struct XTokens {
+    default: Arc<XToken>,
+    default_token: Option<String>,
+    tokens: HashMap<String, Arc<XToken>>,
+}
+
+impl XTokens {
+    fn new(
+        config_filters: ConfigGrpcFilters,
+        x_token: Option<String>,
+        x_tokens: Vec<ConfigGrpcXToken>,
+    ) -> Self {
+        let config_filters = Arc::new(config_filters);
+        let tokens = x_tokens
+            .into_iter()
+            .map(|x_token| {
+                let config_filters = x_token
+                    .filters
+                    .map(Arc::new)
+                    .unwrap_or_else(|| Arc::clone(&config_filters));
+                let value = XToken::new(
+                    x_token.name,
+                    config_filters,
+                    x_token.subscriptions_max,
+                    x_token.unary_allowed,
+                );
+                (x_token.token, Arc::new(value))
+            })
+            .collect();
+        Self {
+            default: Arc::new(XToken::new(
+                ConfigGrpcXToken::DEFAULT_NAME.to_owned(),
+                config_filters,
+                usize::MAX,
+                None,
+            )),
+            default_token: x_token,
+            tokens,
+        }
+    }
+
+    // `None` if the token is not valid
+    fn get(&self, token: Option<&str>) -> Option<Arc<XToken>> {
+        match (
+            token.and_then(|token| self.tokens.get(token)),
+            &self.default_token,
+        ) {
+            (Some(x_token), _) => Some(Arc::clone(x_token)),
+            (None, Some(default_token)) if token == Some(default_token.as_str()) => {
+                Some(Arc::clone(&self.default))
+            }
+            (None, None) if self.tokens.is_empty() => Some(Arc::clone(&self.default)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GrpcService {
     config_snapshot_client_channel_capacity: usize,
     config_channel_capacity: usize,
-    config_filters: Arc<ConfigGrpcFilters>,
+    config_ping_interval: Duration,
+    config_overflow: ConfigGrpcOverflow,
+    x_token_default: Arc<XToken>,
     blocks_meta: Option<BlockMetaStorage>,
     subscribe_id: AtomicUsize,
     snapshot_rx: Mutex<Option<crossbeam_channel::Receiver<Box<Message>>>>,
-    broadcast_tx: broadcast::Sender<(CommitmentLevel, Arc<Vec<Arc<Message>>>)>,
+    broadcast_tx: broadcast::Sender<BroadcastedMessage>,
+    replay_stored_slots_tx: Option<mpsc::UnboundedSender<ReplayStoredSlotsRequest>>,
     debug_clients_tx: Option<mpsc::UnboundedSender<DebugClientMessage>>,
+    filters_index: Arc<FiltersIndex>,
 }
 
 impl GrpcService {
@@ -742,8 +1396,9 @@ impl GrpcService {
         is_reload: bool,
     ) -> anyhow::Result<(
         Option<crossbeam_channel::Sender<Box<Message>>>,
-        mpsc::UnboundedSender<Arc<Message>>,
+        mpsc::UnboundedSender<PluginMessage>,
         Arc<Notify>,
+        Arc<FiltersIndex>,
     )> {
         // Bind service address
         let incoming = TcpIncoming::new(
@@ -752,6 +1407,8 @@ impl GrpcService {
             Some(Duration::from_secs(20)), // tcp_keepalive
         )
         .map_err(|error| anyhow::anyhow!(error))?;
+        metrics::update_config_grpc(&config);
+        let ping_interval = config.ping_interval();
 
         // Snapshot channel
         let (snapshot_tx, snapshot_rx) = match config.snapshot_plugin_channel_capacity {
@@ -774,6 +1431,17 @@ impl GrpcService {
         // Messages to clients combined by commitment
         let (broadcast_tx, _) = broadcast::channel(config.channel_capacity);
 
+        // Requests for re-broadcast of stored messages
+        let (replay_stored_slots_tx, replay_stored_slots_rx) = mpsc::unbounded_channel();
+        let replay_stored_slots = config.replay_stored_slots;
+
+        // Account and transaction filters of all clients, clients subscribed later can request
+        // any stored account update with `from_slot`
+        let filters_index = Arc::new(FiltersIndex::new(replay_stored_slots > 0));
+
+        // Limits of clients by `x-token`
+        let x_tokens = XTokens::new(config.filters, config.x_token, config.x_tokens);
+
         // gRPC server builder with optional TLS
         let mut server_builder = Server::builder();
         if let Some(tls_config) = &config.tls_config {
@@ -792,12 +1460,16 @@ impl GrpcService {
         let mut service = GeyserServer::new(Self {
             config_snapshot_client_channel_capacity: config.snapshot_client_channel_capacity,
             config_channel_capacity: config.channel_capacity,
-            config_filters: Arc::new(config.filters),
+            config_ping_interval: ping_interval,
+            config_overflow: config.overflow,
+            x_token_default: Arc::clone(&x_tokens.default),
             blocks_meta,
             subscribe_id: AtomicUsize::new(0),
             snapshot_rx: Mutex::new(snapshot_rx),
             broadcast_tx: broadcast_tx.clone(),
+            replay_stored_slots_tx: (replay_stored_slots > 0).then_some(replay_stored_slots_tx),
             debug_clients_tx,
+            filters_index: Arc::clone(&filters_index),
         })
         .max_decoding_message_size(max_decoding_message_size);
         for encoding in config.compression.accept {
@@ -809,10 +1481,11 @@ impl GrpcService {
 
         // Run geyser message loop
         let (messages_tx, messages_rx) = mpsc::unbounded_channel();
+        let config_geyser_loop = config.geyser_loop.clone();
         spawn_blocking(move || {
             Builder::new_multi_thread()
                 .thread_name_fn(crate::get_thread_name)
-                .worker_threads(4)
+                .worker_threads(config_geyser_loop.worker_threads)
                 .enable_all()
                 .build()
                 .expect("Failed to create a new runtime for geyser loop")
@@ -820,7 +1493,11 @@ impl GrpcService {
                     messages_rx,
                     blocks_meta_tx,
                     broadcast_tx,
+                    replay_stored_slots_rx,
+                    replay_stored_slots,
                     block_fail_action,
+                    Arc::clone(&filters_index),
+                    config_geyser_loop,
                 ));
         });
 
@@ -834,15 +1511,16 @@ impl GrpcService {
 
             server_builder
                 .http2_keepalive_interval(Some(Duration::from_secs(5)))
-                .layer(interceptor(move |request: Request<()>| {
-                    if let Some(x_token) = &config.x_token {
-                        match request.metadata().get("x-token") {
-                            Some(token) if x_token == token => Ok(request),
-                            _ => Err(Status::unauthenticated("No valid auth token")),
-                        }
-                    } else {
-                        Ok(request)
-                    }
+                .layer(interceptor(move |mut request: Request<()>| {
+                    let token = request
+                        .metadata()
+                        .get("x-token")
+                        .and_then(|token| token.to_str().ok());
+                    let Some(x_token) = x_tokens.get(token) else {
+                        return Err(Status::unauthenticated("No valid auth token"));
+                    };
+                    request.extensions_mut().insert(x_token);
+                    Ok(request)
                 }))
                 .add_service(health_service)
                 .add_service(service)
@@ -850,305 +1528,498 @@ impl GrpcService {
                 .await
         });
 
-        Ok((snapshot_tx, messages_tx, shutdown))
+        Ok((snapshot_tx, messages_tx, shutdown, filters_index))
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn geyser_loop(
-        mut messages_rx: mpsc::UnboundedReceiver<Arc<Message>>,
+        mut messages_rx: mpsc::UnboundedReceiver<PluginMessage>,
         blocks_meta_tx: Option<mpsc::UnboundedSender<Message>>,
-        broadcast_tx: broadcast::Sender<(CommitmentLevel, Arc<Vec<Arc<Message>>>)>,
+        broadcast_tx: broadcast::Sender<BroadcastedMessage>,
+        mut replay_stored_slots_rx: mpsc::UnboundedReceiver<ReplayStoredSlotsRequest>,
+        replay_stored_slots: u64,
         block_fail_action: ConfigBlockFailAction,
+        filters_index: Arc<FiltersIndex>,
+        config: ConfigGrpcGeyserLoop,
     ) {
-        const PROCESSED_MESSAGES_MAX: usize = 31;
-        const PROCESSED_MESSAGES_SLEEP: Duration = Duration::from_millis(10);
+        let processed_messages_max = config.processed_messages_max;
+        let processed_messages_sleep = config.processed_messages_sleep();
 
         let mut messages: BTreeMap<u64, SlotMessages> = Default::default();
-        let mut processed_messages = Vec::with_capacity(PROCESSED_MESSAGES_MAX);
+        let mut processed_messages = Vec::with_capacity(processed_messages_max);
         let mut processed_first_slot = None;
-        let processed_sleep = sleep(PROCESSED_MESSAGES_SLEEP);
+        let processed_sleep = sleep(processed_messages_sleep);
         tokio::pin!(processed_sleep);
+        let mut replay_stored_slots = ReplayStoredSlots::new(replay_stored_slots);
+        let mut encode_caches = MessagesEncodeCaches::new(config.encoded_batches_max);
+        let mut slot_forks = SlotForks::new(config.keep_slots_before_finalized);
 
         loop {
             tokio::select! {
                 Some(message) = messages_rx.recv() => {
                     MESSAGE_QUEUE_SIZE.dec();
 
-                    // Update metrics
-                    if let Message::Slot(slot_message) = message.as_ref() {
-                        metrics::update_slot_plugin_status(slot_message.status, slot_message.slot);
-                    }
-
-                    // Update blocks info
-                    if let Some(blocks_meta_tx) = &blocks_meta_tx {
-                        if matches!(message.as_ref(), Message::Slot(_) | Message::BlockMeta(_)) {
-                            let _ = blocks_meta_tx.send(message.as_ref().clone());
+                    let message = match message {
+                        PluginMessage::Message(message) => message,
+                        PluginMessage::AccountSkipped { slot, pubkey } => {
+                            messages.entry(slot).or_default().accounts_skipped.insert(pubkey);
+                            continue;
                         }
+                    };
+
+                    // Agave does not always send status for every ancestor, emit them before slot status
+                    let messages_queue = match message.as_ref() {
+                        Message::Slot(slot_message) => match slot_forks.get_ancestors_statuses(slot_message) {
+                            Some(ancestors) => ancestors
+                                .into_iter()
+                                .map(|slot_message| Arc::new(Message::Slot(slot_message)))
+                                .chain(std::iter::once(message))
+                                .collect(),
+                            // status was already emitted as ancestor status, only bookkeeping is updated
+                            None => {
+                                let dead_slots = Self::geyser_loop_update_info(
+                                    &message,
+                                    blocks_meta_tx.as_ref(),
+                                    &mut slot_forks,
+                                );
+                                processed_messages.extend(
+                                    dead_slots
+                                        .into_iter()
+                                        .map(|slot_message| Arc::new(Message::Slot(slot_message))),
+                                );
+                                vec![]
+                            }
+                        },
+                        _ => vec![message],
+                    };
+
+                    for message in messages_queue {
+                        Self::geyser_loop_message(
+                            message,
+                            blocks_meta_tx.as_ref(),
+                            &broadcast_tx,
+                            &mut replay_stored_slots,
+                            &mut encode_caches,
+                            block_fail_action,
+                            &filters_index,
+                            &config,
+                            &mut messages,
+                            &mut processed_messages,
+                            &mut processed_first_slot,
+                            &mut slot_forks,
+                            processed_sleep.as_mut(),
+                        );
+                    }
+                }
+                () = &mut processed_sleep => {
+                    if !processed_messages.is_empty() {
+                        Self::broadcast(&broadcast_tx, &mut replay_stored_slots, &mut encode_caches, &filters_index, CommitmentLevel::Processed, processed_messages);
+                        processed_messages = Vec::with_capacity(processed_messages_max);
                     }
+                    processed_sleep.as_mut().reset(Instant::now() + processed_messages_sleep);
+                }
+                Some(request) = replay_stored_slots_rx.recv() => {
+                    // Subscribe in the same loop, so stored and new messages are not overlapped
+                    let response = replay_stored_slots.get(request.from_slot).map(|messages| ReplayStoredSlotsResponse {
+                        messages,
+                        messages_rx: broadcast_tx.subscribe(),
+                    });
+                    let _ = request.tx.send(response);
+                }
+                else => break,
+            }
+        }
+    }
 
-                    // Remove outdated block reconstruction info
-                    match message.as_ref() {
-                        // On startup we can receive few Confirmed/Finalized slots without BlockMeta message
-                        // With saved first Processed slot we can ignore errors caused by startup process
-                        Message::Slot(msg) if processed_first_slot.is_none() && msg.status == CommitmentLevel::Processed => {
-                            processed_first_slot = Some(msg.slot);
-                        }
-                        Message::Slot(msg) if msg.status == CommitmentLevel::Finalized => {
-                            // keep extra 10 slots
-                            if let Some(msg_slot) = msg.slot.checked_sub(10) {
-                                loop {
-                                    match messages.keys().next().cloned() {
-                                        Some(slot) if slot < msg_slot => {
-                                            if let Some(slot_messages) = messages.remove(&slot) {
-                                                match processed_first_slot {
-                                                    Some(processed_first) if slot <= processed_first => continue,
-                                                    None => continue,
-                                                    _ => {}
-                                                }
-
-                                                if !slot_messages.sealed && slot_messages.finalized_at.is_some() {
-                                                    let mut reasons = vec![];
-                                                    if let Some(block_meta) = slot_messages.block_meta {
-                                                        let block_txn_count = block_meta.executed_transaction_count as usize;
-                                                        let msg_txn_count = slot_messages.transactions.len();
-                                                        if block_txn_count != msg_txn_count {
-                                                            reasons.push("InvalidTxnCount");
-                                                            error!("failed to reconstruct #{slot} -- tx count: {block_txn_count} vs {msg_txn_count}");
-                                                        }
-                                                        let block_entries_count = block_meta.entries_count as usize;
-                                                        let msg_entries_count = slot_messages.entries.len();
-                                                        if block_entries_count != msg_entries_count {
-                                                            reasons.push("InvalidEntriesCount");
-                                                            error!("failed to reconstruct #{slot} -- entries count: {block_entries_count} vs {msg_entries_count}");
-                                                        }
-                                                    } else {
-                                                        reasons.push("NoBlockMeta");
-                                                    }
-                                                    let reason = reasons.join(",");
-
-                                                    metrics::update_invalid_blocks(format!("failed reconstruct {reason}"));
-                                                    match block_fail_action {
-                                                        ConfigBlockFailAction::Log => {
-                                                            error!("failed reconstruct #{slot} {reason}");
-                                                        }
-                                                        ConfigBlockFailAction::Panic => {
-                                                            panic!("failed reconstruct #{slot} {reason}");
-                                                        }
-                                                    }
-                                                }
+    // returns slots on dead forks, notifications are sent with processed messages
+    fn geyser_loop_update_info(
+        message: &Message,
+        blocks_meta_tx: Option<&mpsc::UnboundedSender<Message>>,
+        slot_forks: &mut SlotForks,
+    ) -> Vec<MessageSlot> {
+        // Update metrics
+        if let Message::Slot(slot_message) = message {
+            metrics::update_slot_plugin_status(slot_message.status, slot_message.slot);
+        }
+
+        // Find slots on dead forks
+        let dead_slots = match message {
+            Message::Slot(slot_message) => slot_forks.update(slot_message),
+            _ => vec![],
+        };
+
+        // Update blocks info
+        if let Some(blocks_meta_tx) = blocks_meta_tx {
+            if matches!(message, Message::Slot(_) | Message::BlockMeta(_)) {
+                let _ = blocks_meta_tx.send(message.clone());
+            }
+        }
+
+        dead_slots
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn geyser_loop_message(
+        message: Arc<Message>,
+        blocks_meta_tx: Option<&mpsc::UnboundedSender<Message>>,
+        broadcast_tx: &broadcast::Sender<BroadcastedMessage>,
+        replay_stored_slots: &mut ReplayStoredSlots,
+        encode_caches: &mut MessagesEncodeCaches,
+        block_fail_action: ConfigBlockFailAction,
+        filters_index: &FiltersIndex,
+        config: &ConfigGrpcGeyserLoop,
+        messages: &mut BTreeMap<u64, SlotMessages>,
+        processed_messages: &mut Vec<Arc<Message>>,
+        processed_first_slot: &mut Option<u64>,
+        slot_forks: &mut SlotForks,
+        mut processed_sleep: Pin<&mut Sleep>,
+    ) {
+        let dead_slots = Self::geyser_loop_update_info(&message, blocks_meta_tx, slot_forks);
+
+        // Remove outdated block reconstruction info
+        match message.as_ref() {
+            // On startup we can receive few Confirmed/Finalized slots without BlockMeta message
+            // With saved first Processed slot we can ignore errors caused by startup process
+            Message::Slot(msg)
+                if processed_first_slot.is_none() && msg.status == CommitmentLevel::Processed =>
+            {
+                *processed_first_slot = Some(msg.slot);
+            }
+            Message::Slot(msg) if msg.status == CommitmentLevel::Finalized => {
+                // keep extra slots
+                if let Some(msg_slot) = msg.slot.checked_sub(config.keep_slots_before_finalized) {
+                    loop {
+                        match messages.keys().next().cloned() {
+                            Some(slot) if slot < msg_slot => {
+                                if let Some(slot_messages) = messages.remove(&slot) {
+                                    match *processed_first_slot {
+                                        Some(processed_first) if slot <= processed_first => {
+                                            continue
+                                        }
+                                        None => continue,
+                                        _ => {}
+                                    }
+
+                                    if !slot_messages.sealed && slot_messages.finalized_at.is_some()
+                                    {
+                                        let mut reasons = vec![];
+                                        if let Some(block_meta) = slot_messages.block_meta {
+                                            let block_txn_count =
+                                                block_meta.executed_transaction_count as usize;
+                                            let msg_txn_count = slot_messages.transactions.len();
+                                            if block_txn_count != msg_txn_count {
+                                                reasons.push("InvalidTxnCount");
+                                                error!("failed to reconstruct #{slot} -- tx count: {block_txn_count} vs {msg_txn_count}");
+                                            }
+                                            let block_entries_count =
+                                                block_meta.entries_count as usize;
+                                            let msg_entries_count = slot_messages.entries.len();
+                                            if block_entries_count != msg_entries_count {
+                                                reasons.push("InvalidEntriesCount");
+                                                error!("failed to reconstruct #{slot} -- entries count: {block_entries_count} vs {msg_entries_count}");
+                                            }
+                                        } else {
+                                            reasons.push("NoBlockMeta");
+                                        }
+                                        let reason = reasons.join(",");
+
+                                        metrics::update_invalid_blocks(format!(
+                                            "failed reconstruct {reason}"
+                                        ));
+                                        match block_fail_action {
+                                            ConfigBlockFailAction::Log => {
+                                                error!("failed reconstruct #{slot} {reason}");
+                                            }
+                                            ConfigBlockFailAction::Panic => {
+                                                panic!("failed reconstruct #{slot} {reason}");
                                             }
                                         }
-                                        _ => break,
                                     }
                                 }
                             }
+                            _ => break,
                         }
-                        _ => {}
                     }
+                }
+            }
+            _ => {}
+        }
 
-                    // Update block reconstruction info
-                    let slot_messages = messages.entry(message.get_slot()).or_default();
-                    if !matches!(message.as_ref(), Message::Slot(_)) {
-                        slot_messages.messages.push(Some(Arc::clone(&message)));
-
-                        // If we already build Block message, new message will be a problem
-                        if slot_messages.sealed && !(matches!(message.as_ref(), Message::Entry(_)) && slot_messages.entries_count == 0) {
-                            metrics::update_invalid_blocks(format!("unexpected message {}", message.kind()));
-                            match block_fail_action {
-                                ConfigBlockFailAction::Log => {
-                                    error!("unexpected message #{} -- {} (invalid order)", message.get_slot(), message.kind());
-                                }
-                                ConfigBlockFailAction::Panic => {
-                                    panic!("unexpected message #{} -- {} (invalid order)", message.get_slot(), message.kind());
-                                }
-                            }
-                        }
+        // Update block reconstruction info
+        let slot_messages = messages.entry(message.get_slot()).or_default();
+        if !matches!(message.as_ref(), Message::Slot(_)) {
+            slot_messages.messages.push(Some(Arc::clone(&message)));
+
+            // If we already build Block message, new message will be a problem
+            if slot_messages.sealed
+                && !(matches!(message.as_ref(), Message::Entry(_))
+                    && slot_messages.entries_count == 0)
+            {
+                metrics::update_invalid_blocks(format!("unexpected message {}", message.kind()));
+                match block_fail_action {
+                    ConfigBlockFailAction::Log => {
+                        error!(
+                            "unexpected message #{} -- {} (invalid order)",
+                            message.get_slot(),
+                            message.kind()
+                        );
                     }
-                    let mut sealed_block_msg = None;
-                    match message.as_ref() {
-                        Message::BlockMeta(msg) => {
-                            if slot_messages.block_meta.is_some() {
-                                metrics::update_invalid_blocks("unexpected message: BlockMeta (duplicate)");
-                                match block_fail_action {
-                                    ConfigBlockFailAction::Log => {
-                                        error!("unexpected message #{} -- BlockMeta (duplicate)", message.get_slot());
-                                    }
-                                    ConfigBlockFailAction::Panic => {
-                                        panic!("unexpected message #{} -- BlockMeta (duplicate)", message.get_slot());
-                                    }
-                                }
-                            }
-                            slot_messages.block_meta = Some(msg.clone());
-                            sealed_block_msg = slot_messages.try_seal();
-                        }
-                        Message::Transaction(msg) => {
-                            slot_messages.transactions.push(msg.transaction.clone());
-                            sealed_block_msg = slot_messages.try_seal();
-                        }
-                        // Dedup accounts by max write_version
-                        Message::Account(msg) => {
-                            let write_version = msg.account.write_version;
-                            let msg_index = slot_messages.messages.len() - 1;
-                            if let Some(entry) = slot_messages.accounts_dedup.get_mut(&msg.account.pubkey) {
-                                if entry.0 < write_version {
-                                    // We can replace the message, but in this case we will lose the order
-                                    slot_messages.messages[entry.1] = None;
-                                    *entry = (write_version, msg_index);
-                                }
-                            } else {
-                                slot_messages.accounts_dedup.insert(msg.account.pubkey, (write_version, msg_index));
-                            }
+                    ConfigBlockFailAction::Panic => {
+                        panic!(
+                            "unexpected message #{} -- {} (invalid order)",
+                            message.get_slot(),
+                            message.kind()
+                        );
+                    }
+                }
+            }
+        }
+        let mut sealed_block_msg = None;
+        match message.as_ref() {
+            Message::BlockMeta(msg) => {
+                if slot_messages.block_meta.is_some() {
+                    metrics::update_invalid_blocks("unexpected message: BlockMeta (duplicate)");
+                    match block_fail_action {
+                        ConfigBlockFailAction::Log => {
+                            error!(
+                                "unexpected message #{} -- BlockMeta (duplicate)",
+                                message.get_slot()
+                            );
                         }
-                        Message::Entry(msg) => {
-                            slot_messages.entries.push(msg.clone());
-                            sealed_block_msg = slot_messages.try_seal();
+                        ConfigBlockFailAction::Panic => {
+                            panic!(
+                                "unexpected message #{} -- BlockMeta (duplicate)",
+                                message.get_slot()
+                            );
                         }
-                        _ => {}
                     }
-
-                    // Send messages to filter (and to clients)
-                    let mut messages_vec = vec![message];
-                    if let Some(sealed_block_msg) = sealed_block_msg {
-                        messages_vec.push(sealed_block_msg);
+                }
+                slot_messages.block_meta = Some(msg.clone());
+                sealed_block_msg = slot_messages.try_seal();
+            }
+            Message::Transaction(msg) => {
+                slot_messages.transactions.push(msg.transaction.clone());
+                sealed_block_msg = slot_messages.try_seal();
+            }
+            // Dedup accounts by max write_version
+            Message::Account(msg) => {
+                let write_version = msg.account.write_version;
+                let msg_index = slot_messages.messages.len() - 1;
+                if let Some(entry) = slot_messages.accounts_dedup.get_mut(&msg.account.pubkey) {
+                    if entry.0 < write_version {
+                        // We can replace the message, but in this case we will lose the order
+                        slot_messages.messages[entry.1] = None;
+                        *entry = (write_version, msg_index);
                     }
+                } else {
+                    slot_messages
+                        .accounts_dedup
+                        .insert(msg.account.pubkey, (write_version, msg_index));
+                }
+            }
+            Message::Entry(msg) => {
+                slot_messages.entries.push(msg.clone());
+                sealed_block_msg = slot_messages.try_seal();
+            }
+            _ => {}
+        }
 
-                    for message in messages_vec {
-                        if let Message::Slot(slot) = message.as_ref() {
-                            let (mut confirmed_messages, mut finalized_messages) = match slot.status {
-                                CommitmentLevel::Processed => {
-                                    (Vec::with_capacity(1), Vec::with_capacity(1))
-                                }
-                                CommitmentLevel::Confirmed => {
-                                    if let Some(slot_messages) = messages.get_mut(&slot.slot) {
-                                        if !slot_messages.sealed {
-                                            slot_messages.confirmed_at = Some(slot_messages.messages.len());
-                                        }
-                                    }
-
-                                    let vec = messages
-                                        .get(&slot.slot)
-                                        .map(|slot_messages| slot_messages.messages.iter().flatten().cloned().collect())
-                                        .unwrap_or_default();
-                                    (vec, Vec::with_capacity(1))
-                                }
-                                CommitmentLevel::Finalized => {
-                                    if let Some(slot_messages) = messages.get_mut(&slot.slot) {
-                                        if !slot_messages.sealed {
-                                            slot_messages.finalized_at = Some(slot_messages.messages.len());
-                                        }
-                                    }
-
-                                    let vec = messages
-                                        .get_mut(&slot.slot)
-                                        .map(|slot_messages| slot_messages.messages.iter().flatten().cloned().collect())
-                                        .unwrap_or_default();
-                                    (Vec::with_capacity(1), vec)
-                                }
-                            };
-
-                            // processed
-                            processed_messages.push(Arc::clone(&message));
-                            let _ =
-                                broadcast_tx.send((CommitmentLevel::Processed, processed_messages.into()));
-                            processed_messages = Vec::with_capacity(PROCESSED_MESSAGES_MAX);
-                            processed_sleep
-                                .as_mut()
-                                .reset(Instant::now() + PROCESSED_MESSAGES_SLEEP);
-
-                            // confirmed
-                            confirmed_messages.push(Arc::clone(&message));
-                            let _ =
-                                broadcast_tx.send((CommitmentLevel::Confirmed, confirmed_messages.into()));
-
-                            // finalized
-                            finalized_messages.push(message);
-                            let _ =
-                                broadcast_tx.send((CommitmentLevel::Finalized, finalized_messages.into()));
-                        } else {
-                            let mut confirmed_messages = vec![];
-                            let mut finalized_messages = vec![];
-                            if matches!(message.as_ref(), Message::Block(_)) {
-                                if let Some(slot_messages) = messages.get(&message.get_slot()) {
-                                    if let Some(confirmed_at) = slot_messages.confirmed_at {
-                                        confirmed_messages.extend(
-                                            slot_messages.messages.as_slice()[confirmed_at..].iter().filter_map(|x| x.clone())
-                                        );
-                                    }
-                                    if let Some(finalized_at) = slot_messages.finalized_at {
-                                        finalized_messages.extend(
-                                            slot_messages.messages.as_slice()[finalized_at..].iter().filter_map(|x| x.clone())
-                                        );
-                                    }
-                                }
-                            }
+        // Send messages to filter (and to clients)
+        let mut messages_vec = vec![message];
+        if let Some(sealed_block_msg) = sealed_block_msg {
+            messages_vec.push(sealed_block_msg);
+        }
 
-                            processed_messages.push(message);
-                            if processed_messages.len() >= PROCESSED_MESSAGES_MAX
-                                || !confirmed_messages.is_empty()
-                                || !finalized_messages.is_empty()
-                            {
-                                let _ = broadcast_tx
-                                    .send((CommitmentLevel::Processed, processed_messages.into()));
-                                processed_messages = Vec::with_capacity(PROCESSED_MESSAGES_MAX);
-                                processed_sleep
-                                    .as_mut()
-                                    .reset(Instant::now() + PROCESSED_MESSAGES_SLEEP);
+        processed_messages.extend(
+            dead_slots
+                .into_iter()
+                .map(|slot_message| Arc::new(Message::Slot(slot_message))),
+        );
+        for message in messages_vec {
+            if let Message::Slot(slot) = message.as_ref() {
+                let (mut confirmed_messages, mut finalized_messages) = match slot.status {
+                    CommitmentLevel::Processed => (Vec::with_capacity(1), Vec::with_capacity(1)),
+                    CommitmentLevel::Confirmed => {
+                        if let Some(slot_messages) = messages.get_mut(&slot.slot) {
+                            if !slot_messages.sealed {
+                                slot_messages.confirmed_at = Some(slot_messages.messages.len());
                             }
+                        }
 
-                            if !confirmed_messages.is_empty() {
-                                let _ =
-                                    broadcast_tx.send((CommitmentLevel::Confirmed, confirmed_messages.into()));
+                        let vec = messages
+                            .get(&slot.slot)
+                            .map(|slot_messages| {
+                                slot_messages.messages.iter().flatten().cloned().collect()
+                            })
+                            .unwrap_or_default();
+                        (vec, Vec::with_capacity(1))
+                    }
+                    CommitmentLevel::Finalized => {
+                        if let Some(slot_messages) = messages.get_mut(&slot.slot) {
+                            if !slot_messages.sealed {
+                                slot_messages.finalized_at = Some(slot_messages.messages.len());
                             }
+                        }
 
-                            if !finalized_messages.is_empty() {
-                                let _ =
-                                    broadcast_tx.send((CommitmentLevel::Finalized, finalized_messages.into()));
-                            }
+                        let vec = messages
+                            .get_mut(&slot.slot)
+                            .map(|slot_messages| {
+                                slot_messages.messages.iter().flatten().cloned().collect()
+                            })
+                            .unwrap_or_default();
+                        (Vec::with_capacity(1), vec)
+                    }
+                };
+
+                // processed
+                processed_messages.push(Arc::clone(&message));
+                let processed = std::mem::replace(
+                    processed_messages,
+                    Vec::with_capacity(config.processed_messages_max),
+                );
+                Self::broadcast(
+                    broadcast_tx,
+                    replay_stored_slots,
+                    encode_caches,
+                    filters_index,
+                    CommitmentLevel::Processed,
+                    processed,
+                );
+                processed_sleep
+                    .as_mut()
+                    .reset(Instant::now() + config.processed_messages_sleep());
+
+                // confirmed
+                confirmed_messages.push(Arc::clone(&message));
+                Self::broadcast(
+                    broadcast_tx,
+                    replay_stored_slots,
+                    encode_caches,
+                    filters_index,
+                    CommitmentLevel::Confirmed,
+                    confirmed_messages,
+                );
+
+                // finalized
+                finalized_messages.push(message);
+                Self::broadcast(
+                    broadcast_tx,
+                    replay_stored_slots,
+                    encode_caches,
+                    filters_index,
+                    CommitmentLevel::Finalized,
+                    finalized_messages,
+                );
+            } else {
+                let mut confirmed_messages = vec![];
+                let mut finalized_messages = vec![];
+                if matches!(message.as_ref(), Message::Block(_)) {
+                    if let Some(slot_messages) = messages.get(&message.get_slot()) {
+                        if let Some(confirmed_at) = slot_messages.confirmed_at {
+                            confirmed_messages.extend(
+                                slot_messages.messages.as_slice()[confirmed_at..]
+                                    .iter()
+                                    .filter_map(|x| x.clone()),
+                            );
+                        }
+                        if let Some(finalized_at) = slot_messages.finalized_at {
+                            finalized_messages.extend(
+                                slot_messages.messages.as_slice()[finalized_at..]
+                                    .iter()
+                                    .filter_map(|x| x.clone()),
+                            );
                         }
                     }
                 }
-                () = &mut processed_sleep => {
-                    if !processed_messages.is_empty() {
-                        let _ = broadcast_tx.send((CommitmentLevel::Processed, processed_messages.into()));
-                        processed_messages = Vec::with_capacity(PROCESSED_MESSAGES_MAX);
-                    }
-                    processed_sleep.as_mut().reset(Instant::now() + PROCESSED_MESSAGES_SLEEP);
+
+                processed_messages.push(message);
+                if processed_messages.len() >= config.processed_messages_max
+                    || !confirmed_messages.is_empty()
+                    || !finalized_messages.is_empty()
+                {
+                    let processed = std::mem::replace(
+                        processed_messages,
+                        Vec::with_capacity(config.processed_messages_max),
+                    );
+                    Self::broadcast(
+                        broadcast_tx,
+                        replay_stored_slots,
+                        encode_caches,
+                        filters_index,
+                        CommitmentLevel::Processed,
+                        processed,
+                    );
+                    processed_sleep
+                        .as_mut()
+                        .reset(Instant::now() + config.processed_messages_sleep());
+                }
+
+                if !confirmed_messages.is_empty() {
+                    Self::broadcast(
+                        broadcast_tx,
+                        replay_stored_slots,
+                        encode_caches,
+                        filters_index,
+                        CommitmentLevel::Confirmed,
+                        confirmed_messages,
+                    );
+                }
+
+                if !finalized_messages.is_empty() {
+                    Self::broadcast(
+                        broadcast_tx,
+                        replay_stored_slots,
+                        encode_caches,
+                        filters_index,
+                        CommitmentLevel::Finalized,
+                        finalized_messages,
+                    );
                 }
-                else => break,
             }
         }
     }
 
+    fn broadcast(
+        broadcast_tx: &broadcast::Sender<BroadcastedMessage>,
+        replay_stored_slots: &mut ReplayStoredSlots,
+        encode_caches: &mut MessagesEncodeCaches,
+        filters_index: &FiltersIndex,
+        commitment: CommitmentLevel,
+        messages: Vec<Arc<Message>>,
+    ) {
+        let matches = Arc::new(filters_index.get_matches(&messages));
+        let messages = Arc::new(messages);
+        replay_stored_slots.push(commitment, &messages);
+        let _ = broadcast_tx.send((commitment, messages, encode_caches.create(), matches));
+    }
+
+    fn get_x_token<T>(&self, request: &Request<T>) -> Arc<XToken> {
+        request
+            .extensions()
+            .get::<Arc<XToken>>()
+            .map_or_else(|| Arc::clone(&self.x_token_default), Arc::clone)
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn client_loop(
         id: usize,
         endpoint: String,
-        config_filters: Arc<ConfigGrpcFilters>,
-        stream_tx: mpsc::Sender<TonicResult<SubscribeUpdate>>,
+        x_token: Arc<XToken>,
+        stream_tx: mpsc::Sender<TonicResult<FilteredUpdate>>,
         mut client_rx: mpsc::UnboundedReceiver<Option<Filter>>,
         mut snapshot_rx: Option<crossbeam_channel::Receiver<Box<Message>>>,
-        mut messages_rx: broadcast::Receiver<(CommitmentLevel, Arc<Vec<Arc<Message>>>)>,
+        mut messages_rx: broadcast::Receiver<BroadcastedMessage>,
+        replay_stored_slots_tx: Option<mpsc::UnboundedSender<ReplayStoredSlotsRequest>>,
         debug_client_tx: Option<mpsc::UnboundedSender<DebugClientMessage>>,
+        filters_index: Arc<FiltersIndex>,
+        config_overflow: ConfigGrpcOverflow,
         drop_client: impl FnOnce(),
     ) {
-        let mut filter = Filter::new(
-            &SubscribeRequest {
-                accounts: HashMap::new(),
-                slots: HashMap::new(),
-                transactions: HashMap::new(),
-                transactions_status: HashMap::new(),
-                blocks: HashMap::new(),
-                blocks_meta: HashMap::new(),
-                entry: HashMap::new(),
-                commitment: None,
-                accounts_data_slice: Vec::new(),
-                ping: None,
-            },
-            &config_filters,
-        )
-        .expect("empty filter");
-        metrics::update_subscriptions(&endpoint, None, Some(&filter));
+        let mut filter = Filter::new(&SubscribeRequest::default(), &x_token.config_filters)
+            .expect("empty filter");
+        metrics::update_subscriptions(&x_token.name, &endpoint, None, Some(&filter));
 
-        CONNECTIONS_TOTAL.inc();
+        CONNECTIONS_TOTAL.with_label_values(&[&x_token.name]).inc();
         DebugClientMessage::maybe_send(&debug_client_tx, || DebugClientMessage::UpdateFilter {
             id,
             filter: Box::new(filter.clone()),
@@ -1156,20 +2027,33 @@ impl GrpcService {
         info!("client #{id}: new");
 
         let mut is_alive = true;
+        let mut filters_index_version = None;
         if let Some(snapshot_rx) = snapshot_rx.take() {
-            Self::client_loop_snapshot(
+            filters_index_version = Self::client_loop_snapshot(
                 id,
+                &x_token.name,
                 &endpoint,
                 &stream_tx,
                 &mut client_rx,
                 snapshot_rx,
                 &mut is_alive,
                 &mut filter,
+                &filters_index,
             )
             .await;
         }
 
         if is_alive {
+            let mut filters_index_version =
+                filters_index_version.unwrap_or_else(|| filters_index.update(id, Some(&filter)));
+            let mut accounts_coalesce_pending = ClientAccountsCoalesce::default();
+            let accounts_coalesce_sleep = sleep(Duration::ZERO);
+            tokio::pin!(accounts_coalesce_sleep);
+            let mut client_overflow = ClientOverflow::new(config_overflow);
+            let overflow_sleep = sleep(config_overflow.flush_interval());
+            tokio::pin!(overflow_sleep);
+            let mut client_error = None;
+
             'outer: loop {
                 tokio::select! {
                     mut message = client_rx.recv() => {
@@ -1197,10 +2081,38 @@ impl GrpcService {
                                     continue;
                                 }
 
-                                metrics::update_subscriptions(&endpoint, Some(&filter), Some(&filter_new));
+                                metrics::update_subscriptions(&x_token.name, &endpoint, Some(&filter), Some(&filter_new));
+                                if filter.get_accounts_coalesce() != filter_new.get_accounts_coalesce() {
+                                    for message in accounts_coalesce_pending.take_all(&filter) {
+                                        if let Err(error) = client_overflow.send(&stream_tx, filter.get_overflow(), message) {
+                                            client_error = Some(error);
+                                            break 'outer;
+                                        }
+                                    }
+                                    if let Some(FilterAccountsCoalesce::Interval(interval)) = filter_new.get_accounts_coalesce() {
+                                        accounts_coalesce_sleep.as_mut().reset(Instant::now() + interval);
+                                    }
+                                }
+                                // replay only if `from_slot` was changed, same request is not replayed twice
+                                let replay_from_slot = filter_new.get_from_slot().filter(|from_slot| filter.get_from_slot() != Some(*from_slot));
                                 filter = filter_new;
+                                filters_index_version = filters_index.update(id, Some(&filter));
                                 DebugClientMessage::maybe_send(&debug_client_tx, || DebugClientMessage::UpdateFilter { id, filter: Box::new(filter.clone()) });
                                 info!("client #{id}: filter updated");
+
+                                if let Some(msg) = filter.get_batch_filters_msg() {
+                                    if stream_tx.send(Ok(msg)).await.is_err() {
+                                        error!("client #{id}: stream closed");
+                                        break 'outer;
+                                    }
+                                }
+
+                                if let Some(from_slot) = replay_from_slot {
+                                    match Self::client_loop_replay(id, &stream_tx, replay_stored_slots_tx.as_ref(), &filter, &mut client_overflow, from_slot).await {
+                                        Some(messages_rx_new) => messages_rx = messages_rx_new,
+                                        None => break 'outer,
+                                    }
+                                }
                             }
                             Some(None) => {
                                 break 'outer;
@@ -1211,41 +2123,68 @@ impl GrpcService {
                         }
                     }
                     message = messages_rx.recv() => {
-                        let (commitment, messages) = match message {
-                            Ok((commitment, messages)) => (commitment, messages),
+                        let (commitment, messages, cache, matches) = match message {
+                            Ok(message) => message,
                             Err(broadcast::error::RecvError::Closed) => {
                                 break 'outer;
                             },
-                            Err(broadcast::error::RecvError::Lagged(_)) => {
-                                info!("client #{id}: lagged to receive geyser messages");
-                                tokio::spawn(async move {
-                                    let _ = stream_tx.send(Err(Status::internal("lagged"))).await;
-                                });
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                client_error = Some(ClientError::Lagged(skipped));
                                 break 'outer;
                             }
                         };
 
-                        if commitment == filter.get_commitment_level() {
-                            for message in messages.iter() {
-                                for message in filter.get_update(message, Some(commitment)) {
-                                    match stream_tx.try_send(Ok(message)) {
-                                        Ok(()) => {}
-                                        Err(mpsc::error::TrySendError::Full(_)) => {
-                                            error!("client #{id}: lagged to send update");
-                                            tokio::spawn(async move {
-                                                let _ = stream_tx.send(Err(Status::internal("lagged"))).await;
-                                            });
-                                            break 'outer;
-                                        }
-                                        Err(mpsc::error::TrySendError::Closed(_)) => {
-                                            error!("client #{id}: stream closed");
-                                            break 'outer;
+                        if filter.contains_commitment_level(commitment) {
+                            let accounts_coalesce = filter.get_accounts_coalesce();
+                            let mut updates = vec![];
+                            for (index, message) in messages.iter().enumerate() {
+                                if !matches.is_match(index, id, filters_index_version) {
+                                    continue;
+                                }
+
+                                match (accounts_coalesce, message.as_ref()) {
+                                    (Some(_), Message::Account(account)) => {
+                                        let account_updates = filter.get_update(message, Some(commitment), &cache).collect::<Vec<_>>();
+                                        if !account_updates.is_empty() {
+                                            accounts_coalesce_pending.push(commitment, account, account_updates);
+                                            if accounts_coalesce_pending.is_full() {
+                                                updates.extend(accounts_coalesce_pending.take_all(&filter));
+                                            }
                                         }
+                                        continue;
+                                    }
+                                    // slot status is sent after all account writes for the slot
+                                    (Some(FilterAccountsCoalesce::Slot), Message::Slot(slot_message)) => {
+                                        let slot = slot_message.slot;
+                                        updates.extend(accounts_coalesce_pending.take(|item_commitment, item_slot| item_commitment == commitment && item_slot <= slot));
                                     }
+                                    _ => {}
+                                }
+
+                                updates.extend(filter.get_update(message, Some(commitment), &cache));
+                            }
+
+                            for message in filter.get_batch(updates, commitment) {
+                                if let Err(error) = client_overflow.send(&stream_tx, filter.get_overflow(), message) {
+                                    client_error = Some(error);
+                                    break 'outer;
                                 }
                             }
                         }
 
+                        // messages which can not be matched by client filters are skipped before full check
+                        let upgrades_messages = messages
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _message)| matches.is_match(*index, id, filters_index_version))
+                            .map(|(_index, message)| message);
+                        for message in filter.get_commitment_upgrades(upgrades_messages, commitment) {
+                            if let Err(error) = client_overflow.send(&stream_tx, filter.get_overflow(), message) {
+                                client_error = Some(error);
+                                break 'outer;
+                            }
+                        }
+
                         if commitment == CommitmentLevel::Processed && debug_client_tx.is_some() {
                             for message in messages.iter() {
                                 if let Message::Slot(slot_message) = message.as_ref() {
@@ -1254,28 +2193,180 @@ impl GrpcService {
                             }
                         }
                     }
+                    () = &mut accounts_coalesce_sleep, if matches!(filter.get_accounts_coalesce(), Some(FilterAccountsCoalesce::Interval(_))) => {
+                        for message in accounts_coalesce_pending.take_all(&filter) {
+                            if let Err(error) = client_overflow.send(&stream_tx, filter.get_overflow(), message) {
+                                client_error = Some(error);
+                                break 'outer;
+                            }
+                        }
+                        if let Some(FilterAccountsCoalesce::Interval(interval)) = filter.get_accounts_coalesce() {
+                            accounts_coalesce_sleep.as_mut().reset(Instant::now() + interval);
+                        }
+                    }
+                    () = &mut overflow_sleep, if client_overflow.is_pending() => {
+                        if let Err(error) = client_overflow.flush(&stream_tx, filter.get_overflow()) {
+                            client_error = Some(error);
+                            break 'outer;
+                        }
+                        overflow_sleep.as_mut().reset(Instant::now() + config_overflow.flush_interval());
+                    }
                 }
             }
+
+            if let Some(error) = client_error {
+                Self::client_error(id, stream_tx, &filter, &client_overflow, error);
+            }
         }
 
-        CONNECTIONS_TOTAL.dec();
+        filters_index.update(id, None);
+        CONNECTIONS_TOTAL.with_label_values(&[&x_token.name]).dec();
         DebugClientMessage::maybe_send(&debug_client_tx, || DebugClientMessage::Removed { id });
-        metrics::update_subscriptions(&endpoint, Some(&filter), None);
+        metrics::update_subscriptions(&x_token.name, &endpoint, Some(&filter), None);
         info!("client #{id}: removed");
         drop_client();
     }
 
+    fn client_error(
+        id: usize,
+        stream_tx: mpsc::Sender<TonicResult<FilteredUpdate>>,
+        filter: &Filter,
+        client_overflow: &ClientOverflow,
+        error: ClientError,
+    ) {
+        let skipped_batches = match error {
+            ClientError::Full => {
+                error!("client #{id}: lagged to send update");
+                0
+            }
+            ClientError::Lagged(skipped_batches) => {
+                info!("client #{id}: lagged to receive geyser messages");
+                skipped_batches
+            }
+            ClientError::Closed => {
+                error!("client #{id}: stream closed");
+                return;
+            }
+        };
+
+        // number of skipped batches is reported once, in the first `lagged` update
+        let messages = filter
+            .get_commitment_levels()
+            .iter()
+            .enumerate()
+            .map(|(index, commitment)| {
+                FilteredUpdate::new(
+                    vec![],
+                    Some(*commitment as i32),
+                    UpdateOneof::Lagged(SubscribeUpdateLagged {
+                        commitment: *commitment as i32,
+                        last_slot: client_overflow.get_last_slot(*commitment),
+                        skipped_batches: if index == 0 { skipped_batches } else { 0 },
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        tokio::spawn(async move {
+            for message in messages {
+                if stream_tx.send(Ok(message)).await.is_err() {
+                    return;
+                }
+            }
+            let _ = stream_tx
+                .send(Err(Status::resource_exhausted("lagged")))
+                .await;
+        });
+    }
+
+    async fn client_loop_replay(
+        id: usize,
+        stream_tx: &mpsc::Sender<TonicResult<FilteredUpdate>>,
+        replay_stored_slots_tx: Option<&mpsc::UnboundedSender<ReplayStoredSlotsRequest>>,
+        filter: &Filter,
+        client_overflow: &mut ClientOverflow,
+        from_slot: u64,
+    ) -> Option<broadcast::Receiver<BroadcastedMessage>> {
+        info!("client #{id}: going to replay messages from slot {from_slot}");
+
+        let response = match replay_stored_slots_tx {
+            Some(replay_stored_slots_tx) => {
+                let (tx, rx) = oneshot::channel();
+                if replay_stored_slots_tx
+                    .send(ReplayStoredSlotsRequest { from_slot, tx })
+                    .is_err()
+                {
+                    Err(Status::internal("failed to send replay request"))
+                } else {
+                    match rx.await {
+                        Ok(Some(response)) => Ok(response),
+                        Ok(None) => Err(Status::invalid_argument(format!(
+                            "broadcast from {from_slot} is not available"
+                        ))),
+                        Err(_error) => Err(Status::internal("failed to get replay response")),
+                    }
+                }
+            }
+            None => Err(Status::invalid_argument("replay from slot is disabled")),
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(status) => {
+                error!("client #{id}: failed to replay: {}", status.message());
+                let _ = stream_tx.send(Err(status)).await;
+                return None;
+            }
+        };
+
+        for (commitment, messages, cache, _matches) in response.messages.iter() {
+            // stored batches can contain messages for slots before `from_slot`
+            let messages = messages
+                .iter()
+                .filter(|message| message.get_slot() >= from_slot)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if filter.contains_commitment_level(*commitment) {
+                let updates = messages
+                    .iter()
+                    .flat_map(|message| filter.get_update(message, Some(*commitment), cache))
+                    .collect();
+                for message in filter.get_batch(updates, *commitment) {
+                    client_overflow.set_last_slot(&message);
+                    if stream_tx.send(Ok(message)).await.is_err() {
+                        error!("client #{id}: stream closed");
+                        return None;
+                    }
+                }
+            }
+
+            for message in filter.get_commitment_upgrades(&messages, *commitment) {
+                client_overflow.set_last_slot(&message);
+                if stream_tx.send(Ok(message)).await.is_err() {
+                    error!("client #{id}: stream closed");
+                    return None;
+                }
+            }
+        }
+
+        Some(response.messages_rx)
+    }
+
+    // returns version of filters index if filter was received
+    #[allow(clippy::too_many_arguments)]
     async fn client_loop_snapshot(
         id: usize,
+        x_token: &str,
         endpoint: &str,
-        stream_tx: &mpsc::Sender<TonicResult<SubscribeUpdate>>,
+        stream_tx: &mpsc::Sender<TonicResult<FilteredUpdate>>,
         client_rx: &mut mpsc::UnboundedReceiver<Option<Filter>>,
         snapshot_rx: crossbeam_channel::Receiver<Box<Message>>,
         is_alive: &mut bool,
         filter: &mut Filter,
-    ) {
+        filters_index: &FiltersIndex,
+    ) -> Option<u64> {
         info!("client #{id}: going to receive snapshot data");
 
+        let mut filters_index_version = None;
         // we start with default filter, for snapshot we need wait actual filter first
         while *is_alive {
             match client_rx.recv().await {
@@ -1288,9 +2379,23 @@ impl GrpcService {
                         continue;
                     }
 
-                    metrics::update_subscriptions(endpoint, Some(filter), Some(&filter_new));
+                    metrics::update_subscriptions(
+                        x_token,
+                        endpoint,
+                        Some(filter),
+                        Some(&filter_new),
+                    );
                     *filter = filter_new;
+                    // live updates are broadcasted while snapshot is sent, plugin should keep them
+                    filters_index_version = Some(filters_index.update(id, Some(filter)));
                     info!("client #{id}: filter updated");
+
+                    if let Some(msg) = filter.get_batch_filters_msg() {
+                        if stream_tx.send(Ok(msg)).await.is_err() {
+                            error!("client #{id}: stream closed");
+                            *is_alive = false;
+                        }
+                    }
                     break;
                 }
                 Some(None) => {
@@ -1318,7 +2423,8 @@ impl GrpcService {
                 }
             };
 
-            for message in filter.get_update(&message, None) {
+            let cache = MessagesEncodeCache::default();
+            for message in filter.get_update(&message, None, &cache) {
                 if stream_tx.send(Ok(message)).await.is_err() {
                     error!("client #{id}: stream closed");
                     *is_alive = false;
@@ -1326,17 +2432,21 @@ impl GrpcService {
                 }
             }
         }
+
+        filters_index_version
     }
 }
 
 #[tonic::async_trait]
 impl Geyser for GrpcService {
-    type SubscribeStream = ReceiverStream<TonicResult<SubscribeUpdate>>;
+    type SubscribeStream = ReceiverStream<TonicResult<FilteredUpdate>>;
 
     async fn subscribe(
         &self,
         mut request: Request<Streaming<SubscribeRequest>>,
     ) -> TonicResult<Response<Self::SubscribeStream>> {
+        let x_token = self.get_x_token(&request);
+        x_token.subscription_add()?;
         let id = self.subscribe_id.fetch_add(1, Ordering::Relaxed);
 
         let x_request_snapshot = request.metadata().contains_key("x-request-snapshot");
@@ -1354,6 +2464,7 @@ impl Geyser for GrpcService {
         let notify_exit1 = Arc::new(Notify::new());
         let notify_exit2 = Arc::new(Notify::new());
 
+        let ping_interval = self.config_ping_interval;
         let ping_stream_tx = stream_tx.clone();
         let ping_client_tx = client_tx.clone();
         let ping_exit = Arc::clone(&notify_exit1);
@@ -1361,17 +2472,15 @@ impl Geyser for GrpcService {
             let exit = ping_exit.notified();
             tokio::pin!(exit);
 
-            let ping_msg = SubscribeUpdate {
-                filters: vec![],
-                update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
-            };
+            let ping_msg =
+                FilteredUpdate::new(vec![], None, UpdateOneof::Ping(SubscribeUpdatePing {}));
 
             loop {
                 tokio::select! {
                     _ = &mut exit => {
                         break;
                     }
-                    _ = sleep(Duration::from_secs(10)) => {
+                    _ = sleep(ping_interval) => {
                         match ping_stream_tx.try_send(Ok(ping_msg.clone())) {
                             Ok(()) => {}
                             Err(mpsc::error::TrySendError::Full(_)) => {}
@@ -1391,7 +2500,7 @@ impl Geyser for GrpcService {
             .and_then(|h| h.to_str().ok().map(|s| s.to_string()))
             .unwrap_or_else(|| "".to_owned());
 
-        let config_filters = Arc::clone(&self.config_filters);
+        let config_filters = Arc::clone(&x_token.config_filters);
         let incoming_stream_tx = stream_tx.clone();
         let incoming_client_tx = client_tx;
         let incoming_exit = Arc::clone(&notify_exit2);
@@ -1433,18 +2542,23 @@ impl Geyser for GrpcService {
             }
         });
 
+        let x_token_drop = Arc::clone(&x_token);
         tokio::spawn(Self::client_loop(
             id,
             endpoint,
-            Arc::clone(&self.config_filters),
+            x_token,
             stream_tx,
             client_rx,
             snapshot_rx,
             self.broadcast_tx.subscribe(),
+            self.replay_stored_slots_tx.clone(),
             self.debug_clients_tx.clone(),
+            Arc::clone(&self.filters_index),
+            self.config_overflow,
             move || {
                 notify_exit1.notify_one();
                 notify_exit2.notify_one();
+                x_token_drop.subscription_remove();
             },
         ));
 
@@ -1452,6 +2566,8 @@ impl Geyser for GrpcService {
     }
 
     async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::Ping)?;
         let count = request.get_ref().count;
         let response = PongResponse { count };
         Ok(Response::new(response))
@@ -1461,6 +2577,8 @@ impl Geyser for GrpcService {
         &self,
         request: Request<GetLatestBlockhashRequest>,
     ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::GetLatestBlockhash)?;
         if let Some(blocks_meta) = &self.blocks_meta {
             blocks_meta
                 .get_block(
@@ -1486,6 +2604,8 @@ impl Geyser for GrpcService {
         &self,
         request: Request<GetBlockHeightRequest>,
     ) -> Result<Response<GetBlockHeightResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::GetBlockHeight)?;
         if let Some(blocks_meta) = &self.blocks_meta {
             blocks_meta
                 .get_block(
@@ -1506,6 +2626,8 @@ impl Geyser for GrpcService {
         &self,
         request: Request<GetSlotRequest>,
     ) -> Result<Response<GetSlotResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::GetSlot)?;
         if let Some(blocks_meta) = &self.blocks_meta {
             blocks_meta
                 .get_block(
@@ -1522,6 +2644,8 @@ impl Geyser for GrpcService {
         &self,
         request: Request<IsBlockhashValidRequest>,
     ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::IsBlockhashValid)?;
         if let Some(blocks_meta) = &self.blocks_meta {
             let req = request.get_ref();
             blocks_meta
@@ -1534,10 +2658,912 @@ impl Geyser for GrpcService {
 
     async fn get_version(
         &self,
-        _request: Request<GetVersionRequest>,
+        request: Request<GetVersionRequest>,
     ) -> Result<Response<GetVersionResponse>, Status> {
+        self.get_x_token(&request)
+            .check_unary(ConfigGrpcUnaryMethod::GetVersion)?;
         Ok(Response::new(GetVersionResponse {
             version: serde_json::to_string(&GrpcVersionInfo::default()).unwrap(),
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            ClientAccountsCoalesce, ClientOverflow, GrpcService, Message, MessageAccount,
+            MessageAccountInfo, MessageBlockMeta, MessageSlot, ReplayStoredSlots,
+            ReplayStoredSlotsResponse, SlotForks, SlotMessages, XToken, XTokens,
+        },
+        crate::{
+            codec::{
+                FilteredUpdate, FilteredUpdateOneof, MessageEncoded, MessageEncodedKind,
+                MessagesEncodeCache, MessagesEncodeCaches,
+            },
+            config::{
+                ConfigGrpcFilters, ConfigGrpcOverflow, ConfigGrpcUnaryMethod, ConfigGrpcXToken,
+            },
+            filters::{Filter, FilterOverflow, FiltersIndex},
+        },
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            collections::{HashMap, HashSet},
+            sync::Arc,
+            time::{Duration, Instant},
+        },
+        tokio::{
+            sync::{broadcast, mpsc},
+            time::sleep,
+        },
+        tonic::{Code, Result as TonicResult},
+        yellowstone_grpc_proto::{
+            prelude::{
+                subscribe_update::UpdateOneof, CommitmentLevel, OverflowPolicy, SubscribeRequest,
+                SubscribeRequestFilterAccounts, SubscribeUpdateAccount, SubscribeUpdatePing,
+            },
+            prost::bytes::Bytes,
+        },
+    };
+
+    fn create_message_account(pubkey: Pubkey, slot: u64, write_version: u64) -> MessageAccount {
+        MessageAccount {
+            account: MessageAccountInfo {
+                pubkey,
+                lamports: 0,
+                owner: Pubkey::default(),
+                executable: false,
+                rent_epoch: 0,
+                data: vec![],
+                write_version,
+                txn_signature: None,
+            },
+            slot,
+            is_startup: false,
+        }
+    }
+
+    // filter name is used to identify update
+    fn create_update(name: &str) -> Vec<FilteredUpdate> {
+        vec![FilteredUpdate::new(
+            vec![name.to_owned()],
+            None,
+            UpdateOneof::Ping(SubscribeUpdatePing {}),
+        )]
+    }
+
+    fn create_filter(batch: bool) -> Filter {
+        let config = SubscribeRequest {
+            accounts: HashMap::from([("a".to_owned(), SubscribeRequestFilterAccounts::default())]),
+            batch: Some(batch),
+            ..Default::default()
+        };
+        Filter::new(&config, &ConfigGrpcFilters::default()).unwrap()
+    }
+
+    fn create_filter_owner(owner: Pubkey) -> Filter {
+        let config = SubscribeRequest {
+            accounts: HashMap::from([(
+                "a".to_owned(),
+                SubscribeRequestFilterAccounts {
+                    owner: vec![owner.to_string()],
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        Filter::new(&config, &ConfigGrpcFilters::default()).unwrap()
+    }
+
+    fn get_names(updates: Vec<FilteredUpdate>) -> Vec<String> {
+        updates
+            .into_iter()
+            .flat_map(|update| update.filters)
+            .collect()
+    }
+
+    #[test]
+    fn test_slot_messages_updated_account_count() {
+        let pubkey = Pubkey::new_unique();
+        let mut slot_messages = SlotMessages::default();
+        let message = create_message_account(pubkey, 10, 1);
+        slot_messages
+            .messages
+            .push(Some(Arc::new(Message::Account(message))));
+        slot_messages.accounts_dedup.insert(pubkey, (1, 0));
+        // the same account can be dropped by plugin before client subscribed
+        slot_messages.accounts_skipped.insert(pubkey);
+        slot_messages.accounts_skipped.insert(Pubkey::new_unique());
+
+        slot_messages.block_meta = Some(MessageBlockMeta {
+            parent_slot: 9,
+            slot: 10,
+            parent_blockhash: String::new(),
+            blockhash: String::new(),
+            rewards: vec![],
+            num_partitions: None,
+            block_time: None,
+            block_height: None,
+            executed_transaction_count: 0,
+            entries_count: 0,
+        });
+        let message = slot_messages.try_seal().expect("sealed block");
+        let Message::Block(block) = message.as_ref() else {
+            panic!("expected block: {message:?}");
+        };
+        assert_eq!(block.accounts.len(), 1);
+        assert_eq!(block.updated_account_count, 2);
+    }
+
+    #[test]
+    fn test_accounts_coalesce_push_take() {
+        let pubkey_a = Pubkey::new_unique();
+        let pubkey_b = Pubkey::new_unique();
+        let mut coalesce = ClientAccountsCoalesce::default();
+
+        for (commitment, pubkey, slot, write_version, name) in [
+            (CommitmentLevel::Processed, pubkey_a, 10, 1, "a1"),
+            (CommitmentLevel::Processed, pubkey_a, 10, 3, "a3"),
+            (CommitmentLevel::Processed, pubkey_a, 10, 2, "a2"),
+            (CommitmentLevel::Processed, pubkey_b, 11, 1, "b1"),
+            (CommitmentLevel::Confirmed, pubkey_a, 10, 1, "a1_confirmed"),
+        ] {
+            let message = create_message_account(pubkey, slot, write_version);
+            coalesce.push(commitment, &message, create_update(name));
+        }
+
+        // only the newest write is kept
+        let updates = coalesce
+            .take(|commitment, slot| commitment == CommitmentLevel::Processed && slot <= 10);
+        assert_eq!(get_names(updates), vec!["a3"]);
+
+        // older write after take is still ignored
+        let message = create_message_account(pubkey_b, 11, 0);
+        coalesce.push(CommitmentLevel::Processed, &message, create_update("b0"));
+
+        let filter = create_filter(false);
+        assert_eq!(
+            get_names(coalesce.take_all(&filter)),
+            vec!["b1", "a1_confirmed"]
+        );
+        assert!(coalesce.take_all(&filter).is_empty());
+    }
+
+    #[test]
+    fn test_accounts_coalesce_full() {
+        let pubkey = Pubkey::new_unique();
+        let mut coalesce = ClientAccountsCoalesce::default();
+        for write_version in 0..ClientAccountsCoalesce::ITEMS_MAX as u64 {
+            assert!(!coalesce.is_full());
+            let message = create_message_account(pubkey, 10, write_version);
+            coalesce.push(CommitmentLevel::Processed, &message, vec![]);
+        }
+        // replaced updates are counted too
+        assert!(coalesce.is_full());
+        coalesce.take_all(&create_filter(false));
+        assert!(!coalesce.is_full());
+    }
+
+    #[test]
+    fn test_accounts_coalesce_batch() {
+        let pubkey_a = Pubkey::new_unique();
+        let pubkey_b = Pubkey::new_unique();
+        let mut coalesce = ClientAccountsCoalesce::default();
+
+        for (commitment, pubkey) in [
+            (CommitmentLevel::Processed, pubkey_a),
+            (CommitmentLevel::Confirmed, pubkey_a),
+            (CommitmentLevel::Processed, pubkey_b),
+        ] {
+            let message = create_message_account(pubkey, 10, 1);
+            let update = FilteredUpdate {
+                filters: vec!["a".to_owned()],
+                commitment: Some(commitment as i32),
+                slot: Some(10),
+                update_oneof: FilteredUpdateOneof::Encoded(MessageEncoded {
+                    kind: MessageEncodedKind::Account,
+                    data: Bytes::new(),
+                }),
+            };
+            coalesce.push(commitment, &message, vec![update]);
+        }
+
+        // flushed updates are packed into one batch per commitment level
+        let batches = coalesce
+            .take_all(&create_filter(true))
+            .into_iter()
+            .map(|update| match update.update_oneof {
+                FilteredUpdateOneof::Batch { items, .. } => (update.commitment, items.len()),
+                update => panic!("expected batch update: {update:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            batches,
+            vec![
+                (Some(CommitmentLevel::Processed as i32), 2),
+                (Some(CommitmentLevel::Confirmed as i32), 1)
+            ]
+        );
+    }
+
+    fn create_batch(slots: &[u64]) -> Arc<Vec<Arc<Message>>> {
+        Arc::new(
+            slots
+                .iter()
+                .map(|slot| {
+                    Arc::new(Message::Slot(MessageSlot {
+                        slot: *slot,
+                        parent: slot.checked_sub(1),
+                        status: CommitmentLevel::Processed,
+                        dead: false,
+                    }))
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_replay_stored_slots_account_without_clients() {
+        let owner = Pubkey::new_unique();
+        let pubkey = Pubkey::new_unique();
+        let filters_index = FiltersIndex::new(true);
+
+        // account update is kept by plugin while no client is subscribed
+        assert!(filters_index.is_account_interesting(&pubkey, &owner));
+        let (broadcast_tx, _messages_rx) = broadcast::channel(16);
+        let mut replay = ReplayStoredSlots::new(10);
+        let mut message = create_message_account(pubkey, 10, 1);
+        message.account.owner = owner;
+        GrpcService::broadcast(
+            &broadcast_tx,
+            &mut replay,
+            &mut MessagesEncodeCaches::new(1),
+            &filters_index,
+            CommitmentLevel::Processed,
+            vec![Arc::new(Message::Account(message))],
+        );
+
+        // client subscribed after the update receives it with `from_slot`
+        let (replay_tx, mut replay_rx) = mpsc::unbounded_channel();
+        let (stream_tx, mut stream_rx) = mpsc::channel(16);
+        let filter = create_filter_owner(owner);
+        let mut client_overflow = ClientOverflow::new(ConfigGrpcOverflow::default());
+        let (messages_rx, ()) = tokio::join!(
+            GrpcService::client_loop_replay(
+                0,
+                &stream_tx,
+                Some(&replay_tx),
+                &filter,
+                &mut client_overflow,
+                10,
+            ),
+            async {
+                let request = replay_rx.recv().await.expect("replay request");
+                let response =
+                    replay
+                        .get(request.from_slot)
+                        .map(|messages| ReplayStoredSlotsResponse {
+                            messages,
+                            messages_rx: broadcast_tx.subscribe(),
+                        });
+                request.tx.send(response).expect("client is waiting");
+            }
+        );
+        assert!(messages_rx.is_some());
+        let update = stream_rx.try_recv().expect("update").expect("not error");
+        assert_eq!(update.filters, vec!["a".to_owned()]);
+        assert!(update.is_account());
+        assert!(stream_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_replay_stored_slots_disabled() {
+        let mut replay = ReplayStoredSlots::new(0);
+        replay.push(CommitmentLevel::Processed, &create_batch(&[10]));
+        assert!(replay.get(10).is_none());
+        assert!(replay.batches.is_empty());
+    }
+
+    #[test]
+    fn test_replay_stored_slots_eviction() {
+        let mut replay = ReplayStoredSlots::new(2);
+        assert!(replay.get(0).is_none());
+
+        replay.push(CommitmentLevel::Processed, &create_batch(&[9, 10]));
+        assert_eq!(replay.available_from, Some(9));
+        assert!(replay.get(8).is_none());
+        assert_eq!(replay.get(9).map(|batches| batches.len()), Some(1));
+
+        for slot in 11..=13 {
+            replay.push(CommitmentLevel::Processed, &create_batch(&[slot]));
+        }
+        // batch with slot 10 is removed, slot 10 can not be replayed anymore
+        assert_eq!(replay.available_from, Some(11));
+        assert!(replay.get(10).is_none());
+        assert_eq!(replay.get(11).map(|batches| batches.len()), Some(3));
+        assert_eq!(replay.get(13).map(|batches| batches.len()), Some(1));
+        assert_eq!(replay.get(14).map(|batches| batches.len()), Some(0));
+
+        // empty batches are ignored
+        replay.push(CommitmentLevel::Processed, &create_batch(&[]));
+        assert_eq!(replay.batches.len(), 3);
+    }
+
+    fn create_x_tokens(x_token: Option<&str>, x_tokens: &[(&str, &str)]) -> XTokens {
+        XTokens::new(
+            ConfigGrpcFilters::default(),
+            x_token.map(str::to_owned),
+            x_tokens
+                .iter()
+                .map(|(name, token)| ConfigGrpcXToken {
+                    name: (*name).to_owned(),
+                    token: (*token).to_owned(),
+                    filters: None,
+                    subscriptions_max: 1,
+                    unary_allowed: Some(HashSet::from([ConfigGrpcUnaryMethod::Ping])),
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_client_loop_snapshot_live_updates() {
+        let owner = Pubkey::new_unique();
+        let filters_index = Arc::new(FiltersIndex::default());
+        let (stream_tx, mut stream_rx) = mpsc::channel(16);
+        let (client_tx, client_rx) = mpsc::unbounded_channel();
+        let (snapshot_tx, snapshot_rx) = crossbeam_channel::unbounded::<Box<Message>>();
+        let (broadcast_tx, messages_rx) = broadcast::channel(16);
+        let client = tokio::spawn(GrpcService::client_loop(
+            0,
+            "test".to_owned(),
+            Arc::clone(&create_x_tokens(None, &[]).default),
+            stream_tx,
+            client_rx,
+            Some(snapshot_rx),
+            messages_rx,
+            None,
+            None,
+            Arc::clone(&filters_index),
+            ConfigGrpcOverflow::default(),
+            || {},
+        ));
+
+        client_tx.send(Some(create_filter_owner(owner))).unwrap();
+
+        // filter is registered in the index before snapshot is finished
+        let pubkey = Pubkey::new_unique();
+        while !filters_index.is_account_interesting(&pubkey, &owner) {
+            sleep(Duration::from_millis(1)).await;
+        }
+        let mut message = create_message_account(pubkey, 10, 1);
+        message.account.owner = owner;
+        let messages = vec![Arc::new(Message::Account(message))];
+        let matches = Arc::new(filters_index.get_matches(&messages));
+        broadcast_tx
+            .send((
+                CommitmentLevel::Processed,
+                Arc::new(messages),
+                Arc::new(MessagesEncodeCache::default()),
+                matches,
+            ))
+            .unwrap();
+
+        // update broadcasted during snapshot is sent after snapshot
+        drop(snapshot_tx);
+        let update = stream_rx.recv().await.expect("update").expect("not error");
+        assert_eq!(update.filters, vec!["a".to_owned()]);
+        assert!(update.is_account());
+
+        drop(client_tx);
+        client.await.unwrap();
+        assert!(!filters_index.is_account_interesting(&pubkey, &owner));
+    }
+
+    fn get_name(x_tokens: &XTokens, token: Option<&str>) -> Option<String> {
+        x_tokens.get(token).map(|x_token| x_token.name.clone())
+    }
+
+    #[test]
+    fn test_x_tokens_get() {
+        let default = Some(ConfigGrpcXToken::DEFAULT_NAME.to_owned());
+
+        // without auth
+        let x_tokens = create_x_tokens(None, &[]);
+        assert_eq!(get_name(&x_tokens, None), default);
+        assert_eq!(get_name(&x_tokens, Some("token")), default);
+
+        // with `x_token` and `x_tokens`
+        let x_tokens = create_x_tokens(Some("secret"), &[("a", "token-a")]);
+        assert_eq!(get_name(&x_tokens, Some("secret")), default);
+        assert_eq!(get_name(&x_tokens, Some("token-a")), Some("a".to_owned()));
+        assert_eq!(get_name(&x_tokens, Some("token-b")), None);
+        assert_eq!(get_name(&x_tokens, None), None);
+
+        // only `x_tokens`
+        let x_tokens = create_x_tokens(None, &[("a", "token-a")]);
+        assert_eq!(get_name(&x_tokens, Some("token-a")), Some("a".to_owned()));
+        assert_eq!(get_name(&x_tokens, Some("secret")), None);
+        assert_eq!(get_name(&x_tokens, None), None);
+    }
+
+    #[test]
+    fn test_x_token_limits() {
+        let x_tokens = create_x_tokens(None, &[("a", "token-a")]);
+        let x_token = x_tokens.get(Some("token-a")).unwrap();
+
+        assert!(x_token.subscription_add().is_ok());
+        assert_eq!(
+            x_token.subscription_add().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+        x_token.subscription_remove();
+        assert!(x_token.subscription_add().is_ok());
+
+        assert!(x_token.check_unary(ConfigGrpcUnaryMethod::Ping).is_ok());
+        assert_eq!(
+            x_token
+                .check_unary(ConfigGrpcUnaryMethod::GetSlot)
+                .unwrap_err()
+                .code(),
+            Code::PermissionDenied
+        );
+
+        let default: &XToken = &x_tokens.default;
+        assert!(default.check_unary(ConfigGrpcUnaryMethod::GetSlot).is_ok());
+        for _ in 0..8 {
+            assert!(default.subscription_add().is_ok());
+        }
+    }
+
+    fn create_overflow(
+        policy: OverflowPolicy,
+        buffer_max: usize,
+    ) -> (ClientOverflow, FilterOverflow) {
+        let client_overflow = ClientOverflow::new(ConfigGrpcOverflow {
+            buffer_max,
+            ..Default::default()
+        });
+        let overflow = FilterOverflow {
+            policy,
+            sample_rate: 2,
+            ..Default::default()
+        };
+        (client_overflow, overflow)
+    }
+
+    fn create_update_account(name: &str) -> FilteredUpdate {
+        FilteredUpdate::new(
+            vec![name.to_owned()],
+            None,
+            UpdateOneof::Account(SubscribeUpdateAccount::default()),
+        )
+    }
+
+    fn recv_name(rx: &mut mpsc::Receiver<TonicResult<FilteredUpdate>>) -> String {
+        let update = rx.try_recv().expect("update").expect("not error");
+        assert_eq!(update.filters.len(), 1, "expected named update: {update:?}");
+        update.filters[0].clone()
+    }
+
+    fn recv_dropped(
+        rx: &mut mpsc::Receiver<TonicResult<FilteredUpdate>>,
+    ) -> (u64, HashMap<String, u64>) {
+        let update = rx.try_recv().expect("update").expect("not error");
+        match update.update_oneof {
+            FilteredUpdateOneof::Update(UpdateOneof::Dropped(dropped)) => {
+                (dropped.updates, dropped.filters)
+            }
+            update => panic!("expected dropped notice: {update:?}"),
+        }
+    }
+
+    #[test]
+    fn test_overflow_drop_newest() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(2);
+        let (mut client_overflow, overflow) = create_overflow(OverflowPolicy::DropNewest, 1);
+
+        for name in ["a", "b", "c", "c"] {
+            let update = create_update_account(name);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        assert!(client_overflow.is_pending());
+        assert_eq!(recv_name(&mut stream_rx), "a");
+        assert_eq!(recv_name(&mut stream_rx), "b");
+        assert!(stream_rx.try_recv().is_err());
+
+        // dropped notice is sent before next update
+        let update = create_update_account("d");
+        assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        assert_eq!(
+            recv_dropped(&mut stream_rx),
+            (2, HashMap::from([("c".to_owned(), 2)]))
+        );
+        assert_eq!(recv_name(&mut stream_rx), "d");
+        assert!(!client_overflow.is_pending());
+    }
+
+    #[test]
+    fn test_overflow_drop_oldest() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(1);
+        let (mut client_overflow, overflow) = create_overflow(OverflowPolicy::DropOldest, 2);
+
+        for name in ["a", "b", "c", "d"] {
+            let update = create_update_account(name);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        assert_eq!(recv_name(&mut stream_rx), "a");
+        assert!(stream_rx.try_recv().is_err());
+
+        // buffer is limited by `buffer_max`, oldest update is dropped
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert_eq!(
+            recv_dropped(&mut stream_rx),
+            (1, HashMap::from([("b".to_owned(), 1)]))
+        );
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert_eq!(recv_name(&mut stream_rx), "c");
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert_eq!(recv_name(&mut stream_rx), "d");
+        assert!(!client_overflow.is_pending());
+    }
+
+    #[test]
+    fn test_overflow_sample() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(2);
+        let (mut client_overflow, overflow) = create_overflow(OverflowPolicy::Sample, 1);
+
+        for name in ["a", "b", "c"] {
+            let update = create_update_account(name);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        assert_eq!(recv_name(&mut stream_rx), "a");
+        assert_eq!(recv_name(&mut stream_rx), "b");
+
+        // while overflowed only every `sample_rate`-th account update is sent
+        for name in ["d", "e"] {
+            let update = create_update_account(name);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        assert_eq!(
+            recv_dropped(&mut stream_rx),
+            (2, HashMap::from([("c".to_owned(), 1), ("d".to_owned(), 1)]))
+        );
+        assert_eq!(recv_name(&mut stream_rx), "e");
+
+        // stream is not overflowed anymore, updates are not sampled
+        let update = create_update_account("f");
+        assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        assert_eq!(recv_name(&mut stream_rx), "f");
+        assert!(!client_overflow.is_pending());
+    }
+
+    #[test]
+    fn test_overflow_drop_batch() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(1);
+        let (mut client_overflow, overflow) = create_overflow(OverflowPolicy::DropNewest, 1);
+
+        let item = MessageEncoded {
+            kind: MessageEncodedKind::Account,
+            data: Bytes::new(),
+        };
+        let batch = FilteredUpdate {
+            filters: vec![],
+            commitment: None,
+            slot: None,
+            update_oneof: FilteredUpdateOneof::Batch {
+                filters: vec![("a".to_owned(), 3), ("b".to_owned(), 1)],
+                items: vec![
+                    (vec![0, 1], item.clone()),
+                    (vec![0], item.clone()),
+                    (vec![0], item),
+                ],
+            },
+        };
+        assert!(client_overflow
+            .send(&stream_tx, overflow, create_update_account("a"))
+            .is_ok());
+        assert!(client_overflow.send(&stream_tx, overflow, batch).is_ok());
+        assert_eq!(recv_name(&mut stream_rx), "a");
+
+        // every update in batch is counted
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert_eq!(
+            recv_dropped(&mut stream_rx),
+            (3, HashMap::from([("a".to_owned(), 3), ("b".to_owned(), 1)]))
+        );
+    }
+
+    #[test]
+    fn test_overflow_rate_limit() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(10);
+        let (mut client_overflow, mut overflow) = create_overflow(OverflowPolicy::DropNewest, 1);
+        overflow.rate_limit = Some(2);
+
+        for name in ["a", "b", "c", "d"] {
+            let update = create_update_account(name);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        assert_eq!(recv_name(&mut stream_rx), "a");
+        assert_eq!(recv_name(&mut stream_rx), "b");
+
+        // dropped notice is not sent until next rate window
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert!(stream_rx.try_recv().is_err());
+
+        client_overflow.rate_window = Instant::now() - Duration::from_secs(1);
+        let update = create_update_account("e");
+        assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        assert_eq!(
+            recv_dropped(&mut stream_rx),
+            (2, HashMap::from([("c".to_owned(), 1), ("d".to_owned(), 1)]))
+        );
+        assert_eq!(recv_name(&mut stream_rx), "e");
+
+        // dropped notice counted by rate limit
+        let update = create_update_account("f");
+        assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        assert!(stream_rx.try_recv().is_err());
+        assert!(client_overflow.is_pending());
+    }
+
+    #[test]
+    fn test_overflow_last_slots() {
+        let (stream_tx, mut stream_rx) = mpsc::channel(1);
+        let (mut client_overflow, overflow) = create_overflow(OverflowPolicy::DropOldest, 2);
+
+        for slot in [10, 11] {
+            let mut update = create_update_account("a");
+            update.commitment = Some(CommitmentLevel::Confirmed as i32);
+            update.slot = Some(slot);
+            assert!(client_overflow.send(&stream_tx, overflow, update).is_ok());
+        }
+        // update for slot 11 is buffered
+        assert_eq!(
+            client_overflow.get_last_slot(CommitmentLevel::Confirmed),
+            Some(10)
+        );
+        assert_eq!(
+            client_overflow.get_last_slot(CommitmentLevel::Processed),
+            None
+        );
+
+        assert_eq!(recv_name(&mut stream_rx), "a");
+        assert!(client_overflow.flush(&stream_tx, overflow).is_ok());
+        assert_eq!(
+            client_overflow.get_last_slot(CommitmentLevel::Confirmed),
+            Some(11)
+        );
+    }
+
+    fn create_slot(slot: u64, parent: Option<u64>, status: CommitmentLevel) -> MessageSlot {
+        MessageSlot {
+            slot,
+            parent,
+            status,
+            dead: false,
+        }
+    }
+
+    fn update_slot_forks(forks: &mut SlotForks, slots: &[(u64, Option<u64>)]) {
+        for (slot, parent) in slots {
+            let dead = forks.update(&create_slot(*slot, *parent, CommitmentLevel::Processed));
+            assert!(dead.is_empty());
+        }
+    }
+
+    fn get_dead_slots(dead: Vec<MessageSlot>) -> Vec<(u64, Option<u64>)> {
+        dead.into_iter()
+            .map(|message| {
+                assert!(message.dead);
+                assert_eq!(message.status, CommitmentLevel::Processed);
+                (message.slot, message.parent)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_slot_forks_dead_sibling() {
+        let mut forks = SlotForks::new(10);
+        // 3 is a sibling of finalized chain 1 -> 2 -> 4
+        update_slot_forks(
+            &mut forks,
+            &[(1, Some(0)), (2, Some(1)), (3, Some(1)), (4, Some(2))],
+        );
+
+        let dead = forks.update(&create_slot(4, Some(2), CommitmentLevel::Finalized));
+        assert_eq!(get_dead_slots(dead), vec![(3, Some(1))]);
+        assert!(forks.parents.is_empty());
+
+        // dead slots are reported once
+        let dead = forks.update(&create_slot(4, Some(2), CommitmentLevel::Finalized));
+        assert!(dead.is_empty());
+    }
+
+    #[test]
+    fn test_slot_forks_dead_descendant() {
+        let mut forks = SlotForks::new(10);
+        // 5 and 6 are built on dead slot 3, 7 is built on finalized slot
+        update_slot_forks(
+            &mut forks,
+            &[
+                (1, Some(0)),
+                (2, Some(1)),
+                (3, Some(1)),
+                (4, Some(2)),
+                (5, Some(3)),
+                (6, Some(5)),
+                (7, Some(4)),
+            ],
+        );
+
+        let dead = forks.update(&create_slot(4, Some(2), CommitmentLevel::Finalized));
+        assert_eq!(
+            get_dead_slots(dead),
+            vec![(3, Some(1)), (5, Some(3)), (6, Some(5))]
+        );
+        assert_eq!(forks.parents.keys().copied().collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn test_slot_forks_unknown_parent() {
+        let mut forks = SlotForks::new(10);
+        // parent of 9 is not known, 12 is received without parent
+        update_slot_forks(
+            &mut forks,
+            &[(5, Some(4)), (10, Some(9)), (11, Some(10)), (12, None)],
+        );
+
+        // chain is collected while parents are known, slots below are not reported
+        let dead = forks.update(&create_slot(11, None, CommitmentLevel::Finalized));
+        assert!(dead.is_empty());
+        assert_eq!(forks.parents.keys().copied().collect::<Vec<_>>(), vec![12]);
+
+        // parent of 12 is received later, 12 is not an ancestor of next finalized slot
+        update_slot_forks(&mut forks, &[(12, Some(10))]);
+        let dead = forks.update(&create_slot(13, Some(11), CommitmentLevel::Finalized));
+        assert_eq!(get_dead_slots(dead), vec![(12, Some(10))]);
+    }
+
+    fn get_ancestors_slots(ancestors: Option<Vec<MessageSlot>>) -> Option<Vec<(u64, Option<u64>)>> {
+        ancestors.map(|ancestors| {
+            ancestors
+                .into_iter()
+                .map(|message| (message.slot, message.parent))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_slot_forks_ancestors_statuses() {
+        let mut forks = SlotForks::new(10);
+        update_slot_forks(
+            &mut forks,
+            &[
+                (10, Some(9)),
+                (11, Some(10)),
+                (12, Some(11)),
+                (13, Some(12)),
+            ],
+        );
+
+        // processed statuses are always emitted as is
+        let message = create_slot(13, Some(12), CommitmentLevel::Processed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+
+        // nothing is emitted without previous status
+        let message = create_slot(10, Some(9), CommitmentLevel::Confirmed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+
+        // parent is taken from known slots if not set in the message
+        let message = create_slot(13, None, CommitmentLevel::Confirmed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![(11, Some(10)), (12, Some(11))])
+        );
+
+        // finalized statuses are tracked separately
+        let message = create_slot(11, Some(10), CommitmentLevel::Finalized);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_slot_forks_ancestors_statuses_unknown_parent() {
+        let mut forks = SlotForks::new(10);
+        update_slot_forks(&mut forks, &[(12, Some(11)), (13, Some(12))]);
+
+        let message = create_slot(5, Some(4), CommitmentLevel::Finalized);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+
+        // walk stops on the slot without known parent
+        let message = create_slot(13, Some(12), CommitmentLevel::Finalized);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![(12, Some(11))])
+        );
+    }
+
+    #[test]
+    fn test_slot_forks_ancestors_statuses_real_after_synthesized() {
+        let mut forks = SlotForks::new(10);
+        update_slot_forks(&mut forks, &[(11, Some(10)), (12, Some(11))]);
+
+        let message = create_slot(10, Some(9), CommitmentLevel::Confirmed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+        let message = create_slot(12, Some(11), CommitmentLevel::Confirmed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![(11, Some(10))])
+        );
+
+        // real status after synthesized one is intentionally dropped, status is emitted once
+        let message = create_slot(11, Some(10), CommitmentLevel::Confirmed);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            None
+        );
+
+        // status of the same slot with other commitment level is not affected
+        let message = create_slot(11, Some(10), CommitmentLevel::Finalized);
+        assert_eq!(
+            get_ancestors_slots(forks.get_ancestors_statuses(&message)),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_geyser_loop_update_info_emitted_status() {
+        let mut forks = SlotForks::new(10);
+        update_slot_forks(&mut forks, &[(10, Some(9)), (11, Some(10)), (12, Some(11))]);
+        let (blocks_meta_tx, mut blocks_meta_rx) = mpsc::unbounded_channel();
+
+        for slot in [10, 12] {
+            let message = create_slot(slot, Some(slot - 1), CommitmentLevel::Confirmed);
+            let ancestors = forks.get_ancestors_statuses(&message).expect("not emitted");
+            for message in ancestors.into_iter().chain(std::iter::once(message)) {
+                let dead = GrpcService::geyser_loop_update_info(
+                    &Message::Slot(message),
+                    Some(&blocks_meta_tx),
+                    &mut forks,
+                );
+                assert!(dead.is_empty());
+            }
+        }
+        for slot in [10, 11, 12] {
+            assert!(matches!(
+                blocks_meta_rx.try_recv(),
+                Ok(Message::Slot(message)) if message.slot == slot
+            ));
+        }
+
+        // real status after synthesized one is not broadcasted, but blocks info is updated
+        let message = create_slot(11, Some(10), CommitmentLevel::Confirmed);
+        assert!(forks.get_ancestors_statuses(&message).is_none());
+        let dead = GrpcService::geyser_loop_update_info(
+            &Message::Slot(message),
+            Some(&blocks_meta_tx),
+            &mut forks,
+        );
+        assert!(dead.is_empty());
+        assert!(matches!(
+            blocks_meta_rx.try_recv(),
+            Ok(Message::Slot(message)) if message.slot == 11 && message.status == CommitmentLevel::Confirmed
+        ));
+        assert!(blocks_meta_rx.try_recv().is_err());
+    }
+}
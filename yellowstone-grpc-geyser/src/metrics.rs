@@ -1,5 +1,9 @@
 use {
-    crate::{config::ConfigPrometheus, filters::Filter, version::VERSION as VERSION_INFO},
+    crate::{
+        config::{ConfigGrpc, ConfigPrometheus},
+        filters::Filter,
+        version::VERSION as VERSION_INFO,
+    },
     agave_geyser_plugin_interface::geyser_plugin_interface::SlotStatus,
     http_body_util::{combinators::BoxBody, BodyExt, Empty as BodyEmpty, Full as BodyFull},
     hyper::{
@@ -54,13 +58,19 @@ lazy_static::lazy_static! {
         "message_queue_size", "Size of geyser message queue"
     ).unwrap();
 
-    pub static ref CONNECTIONS_TOTAL: IntGauge = IntGauge::new(
-        "connections_total", "Total number of connections to gRPC service"
+    pub static ref CONNECTIONS_TOTAL: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("connections_total", "Total number of connections to gRPC service"),
+        &["x_token"]
     ).unwrap();
 
     static ref SUBSCRIPTIONS_TOTAL: IntGaugeVec = IntGaugeVec::new(
         Opts::new("subscriptions_total", "Total number of subscriptions to gRPC service"),
-        &["endpoint", "subscription"]
+        &["x_token", "endpoint", "subscription"]
+    ).unwrap();
+
+    static ref CONFIG_GRPC: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("config_grpc", "Effective values of gRPC service runtime and batching options"),
+        &["option"]
     ).unwrap();
 }
 
@@ -193,6 +203,7 @@ impl PrometheusService {
             register!(MESSAGE_QUEUE_SIZE);
             register!(CONNECTIONS_TOTAL);
             register!(SUBSCRIPTIONS_TOTAL);
+            register!(CONFIG_GRPC);
 
             VERSION
                 .with_label_values(&[
@@ -335,16 +346,54 @@ pub fn update_invalid_blocks(reason: impl AsRef<str>) {
     INVALID_FULL_BLOCKS.with_label_values(&["all"]).inc();
 }
 
-pub fn update_subscriptions(endpoint: &str, old: Option<&Filter>, new: Option<&Filter>) {
+pub fn update_config_grpc(config: &ConfigGrpc) {
+    for (option, value) in [
+        (
+            "geyser_loop_worker_threads",
+            config.geyser_loop.worker_threads as i64,
+        ),
+        (
+            "geyser_loop_processed_messages_max",
+            config.geyser_loop.processed_messages_max as i64,
+        ),
+        (
+            "geyser_loop_processed_messages_sleep_ms",
+            config.geyser_loop.processed_messages_sleep_ms as i64,
+        ),
+        (
+            "geyser_loop_keep_slots_before_finalized",
+            config.geyser_loop.keep_slots_before_finalized as i64,
+        ),
+        (
+            "geyser_loop_encoded_batches_max",
+            config.geyser_loop.encoded_batches_max as i64,
+        ),
+        ("ping_interval_ms", config.ping_interval_ms as i64),
+        ("overflow_buffer_max", config.overflow.buffer_max as i64),
+        (
+            "overflow_flush_interval_ms",
+            config.overflow.flush_interval_ms as i64,
+        ),
+    ] {
+        CONFIG_GRPC.with_label_values(&[option]).set(value);
+    }
+}
+
+pub fn update_subscriptions(
+    x_token: &str,
+    endpoint: &str,
+    old: Option<&Filter>,
+    new: Option<&Filter>,
+) {
     for (multiplier, filter) in [(-1, old), (1, new)] {
         if let Some(filter) = filter {
             SUBSCRIPTIONS_TOTAL
-                .with_label_values(&[endpoint, "grpc_total"])
+                .with_label_values(&[x_token, endpoint, "grpc_total"])
                 .add(multiplier);
 
             for (name, value) in filter.get_metrics() {
                 SUBSCRIPTIONS_TOTAL
-                    .with_label_values(&[endpoint, name])
+                    .with_label_values(&[x_token, endpoint, name])
                     .add((value as i64) * multiplier);
             }
         }
@@ -1,7 +1,8 @@
 use {
     crate::{
         config::Config,
-        grpc::{GrpcService, Message},
+        filters::FiltersIndex,
+        grpc::{GrpcService, Message, PluginMessage},
         metrics::{self, PrometheusService, MESSAGE_QUEUE_SIZE},
     },
     agave_geyser_plugin_interface::geyser_plugin_interface::{
@@ -9,6 +10,7 @@ use {
         ReplicaEntryInfoVersions, ReplicaTransactionInfoVersions, Result as PluginResult,
         SlotStatus,
     },
+    solana_sdk::pubkey::Pubkey,
     std::{
         concat, env,
         sync::{
@@ -28,14 +30,19 @@ pub struct PluginInner {
     runtime: Runtime,
     snapshot_channel: Mutex<Option<crossbeam_channel::Sender<Box<Message>>>>,
     snapshot_channel_closed: AtomicBool,
-    grpc_channel: mpsc::UnboundedSender<Arc<Message>>,
+    grpc_channel: mpsc::UnboundedSender<PluginMessage>,
     grpc_shutdown: Arc<Notify>,
+    filters_index: Arc<FiltersIndex>,
     prometheus: PrometheusService,
 }
 
 impl PluginInner {
     fn send_message(&self, message: Message) {
-        if self.grpc_channel.send(Arc::new(message)).is_ok() {
+        self.send(PluginMessage::Message(Arc::new(message)));
+    }
+
+    fn send(&self, message: PluginMessage) {
+        if self.grpc_channel.send(message).is_ok() {
             MESSAGE_QUEUE_SIZE.inc();
         }
     }
@@ -74,17 +81,18 @@ impl GeyserPlugin for Plugin {
             .build()
             .map_err(|error| GeyserPluginError::Custom(Box::new(error)))?;
 
-        let (snapshot_channel, grpc_channel, grpc_shutdown, prometheus) =
-            runtime.block_on(async move {
+        let (snapshot_channel, grpc_channel, grpc_shutdown, filters_index, prometheus) = runtime
+            .block_on(async move {
                 let (debug_client_tx, debug_client_rx) = mpsc::unbounded_channel();
-                let (snapshot_channel, grpc_channel, grpc_shutdown) = GrpcService::create(
-                    config.grpc,
-                    config.block_fail_action,
-                    config.debug_clients_http.then_some(debug_client_tx),
-                    is_reload,
-                )
-                .await
-                .map_err(|error| GeyserPluginError::Custom(format!("{error:?}").into()))?;
+                let (snapshot_channel, grpc_channel, grpc_shutdown, filters_index) =
+                    GrpcService::create(
+                        config.grpc,
+                        config.block_fail_action,
+                        config.debug_clients_http.then_some(debug_client_tx),
+                        is_reload,
+                    )
+                    .await
+                    .map_err(|error| GeyserPluginError::Custom(format!("{error:?}").into()))?;
                 let prometheus = PrometheusService::new(
                     config.prometheus,
                     config.debug_clients_http.then_some(debug_client_rx),
@@ -95,6 +103,7 @@ impl GeyserPlugin for Plugin {
                     snapshot_channel,
                     grpc_channel,
                     grpc_shutdown,
+                    filters_index,
                     prometheus,
                 ))
             })?;
@@ -105,6 +114,7 @@ impl GeyserPlugin for Plugin {
             snapshot_channel_closed: AtomicBool::new(false),
             grpc_channel,
             grpc_shutdown,
+            filters_index,
             prometheus,
         });
 
@@ -152,8 +162,16 @@ impl GeyserPlugin for Plugin {
                     }
                 }
             } else {
-                let message = Message::Account((account, slot, is_startup).into());
-                inner.send_message(message);
+                // skip cloning of account data if no client can receive the update
+                let pubkey = Pubkey::try_from(account.pubkey).expect("valid Pubkey");
+                let owner = Pubkey::try_from(account.owner).expect("valid Pubkey");
+                if inner.filters_index.is_account_interesting(&pubkey, &owner) {
+                    let message = Message::Account((account, slot, is_startup).into());
+                    inner.send_message(message);
+                } else {
+                    // keep `updated_account_count` of the block
+                    inner.send(PluginMessage::AccountSkipped { slot, pubkey });
+                }
             }
 
             Ok(())